@@ -5,7 +5,7 @@ import 'hello.world'
 import { hello, d as w } from "c"
 export const e = 3
 const asp = 234
-import hellow from 'hello_world'
+import helloWorld from 'hello_world'
 export { b, afsj, asp as sixmco }
 
 this.a = 1
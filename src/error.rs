@@ -0,0 +1,54 @@
+use oxc::diagnostics::OxcDiagnostic;
+use oxc::span::Span;
+use std::borrow::Cow;
+use std::fmt;
+
+/// Why a module could not be transpiled.
+#[derive(Debug)]
+pub enum TranspileError {
+    /// An error reported by the parser or the transformer.
+    Diagnostic(OxcDiagnostic),
+    /// Syntax the transpiler cannot convert to `System.register`, at `span`.
+    Unsupported {
+        message: Cow<'static, str>,
+        span: Span,
+    },
+}
+
+impl TranspileError {
+    pub fn unsupported(message: impl Into<Cow<'static, str>>, span: Span) -> Self {
+        Self::Unsupported {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+impl fmt::Display for TranspileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Diagnostic(diagnostic) => diagnostic.fmt(f),
+            Self::Unsupported { message, .. } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for TranspileError {}
+
+impl From<OxcDiagnostic> for TranspileError {
+    fn from(diagnostic: OxcDiagnostic) -> Self {
+        Self::Diagnostic(diagnostic)
+    }
+}
+
+/// Renders the error with oxc's reporters, pointing at the offending source.
+impl From<TranspileError> for OxcDiagnostic {
+    fn from(error: TranspileError) -> Self {
+        match error {
+            TranspileError::Diagnostic(diagnostic) => diagnostic,
+            TranspileError::Unsupported { message, span } => {
+                OxcDiagnostic::error(message).with_label(span)
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use oxc::ast_visit::VisitMut;
+use oxc::diagnostics::OxcDiagnostic;
+use std::path::Path;
+
+mod codegen;
+mod error;
+pub mod options;
+mod parse;
+#[cfg(test)]
+mod tests;
+mod transform;
+mod transpiler;
+mod visit;
+
+pub use error::TranspileError;
+pub use options::{GetModuleId, InvalidSystemGlobal, SystemGlobal, SystemJsTranspilerOptions};
+
+/// The result of [`transpile`].
+#[derive(Debug)]
+pub struct TranspileOutput {
+    /// The `System.register` module.
+    pub code: String,
+    /// Name the module is registered under, or `None` for an anonymous registration.
+    pub module_name: Option<String>,
+    /// Sources of the modules it depends on, in the order of the `setters`.
+    pub dependencies: Vec<String>,
+    /// Every name the module exports explicitly, not counting `export *`.
+    pub exports: Vec<String>,
+    /// Whether the module uses top-level `await`, which makes `execute` asynchronous.
+    pub has_top_level_await: bool,
+    /// Warnings of the lowering to ES2015, and about code whose behaviour changes inside
+    /// `System.register`.
+    pub diagnostics: Vec<OxcDiagnostic>,
+}
+
+/// Transpiles the ES module `source_text` at `source_path` into a `System.register` module.
+/// Fails with every syntax error of `source_text`, or with everything that could not be
+/// converted.
+pub fn transpile(
+    source_text: &str,
+    source_path: &Path,
+    options: SystemJsTranspilerOptions,
+) -> Result<TranspileOutput, Vec<TranspileError>> {
+    let allocator = oxc::allocator::Allocator::default();
+    let into_errors = |errors: Vec<OxcDiagnostic>| -> Vec<TranspileError> {
+        errors.into_iter().map(TranspileError::from).collect()
+    };
+    let mut program = parse::parse_program(source_text, &allocator).map_err(into_errors)?;
+    let (scoping, mut diagnostics) =
+        transform::transform_to_es5(&mut program, &allocator, source_path).map_err(into_errors)?;
+    let mut transpiler =
+        transpiler::SystemJsTranspiler::new(options, &allocator, scoping, source_path);
+    transpiler.visit_program(&mut program);
+    if !transpiler.errors.is_empty() {
+        return Err(transpiler.errors);
+    }
+    diagnostics.append(&mut transpiler.diagnostics);
+    Ok(TranspileOutput {
+        code: codegen::generate_code(&program),
+        module_name: transpiler.module_name,
+        dependencies: transpiler
+            .dependencies
+            .iter()
+            .map(|dependency| dependency.source.value.to_string())
+            .collect(),
+        exports: transpiler
+            .explicit_exports
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+        has_top_level_await: transpiler.has_top_level_await,
+        diagnostics,
+    })
+}
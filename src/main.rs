@@ -1,31 +1,253 @@
-use oxc::ast_visit::VisitMut;
-use std::path::Path;
-mod codegen;
-mod options;
-mod parse;
-mod transform;
-mod transpiler;
-mod visit;
-
-fn main() {
-    let transpiler_options = options::SystemJsTranspilerOptions::default();
-    println!("Transpiler Options: {:#?}", transpiler_options);
-    let path = Path::new("example.js");
-    let source_text = std::fs::read_to_string(path)
-        .unwrap_or_else(|err| panic!("{} not found.\n{err}", path.display()));
-    let allocator = oxc::allocator::Allocator::default();
-    let mut program = parse::parse_program(source_text.as_str(), &allocator);
-    transform::transform_to_es5(&mut program, &allocator, path);
-    let mut transpiler = transpiler::SystemJsTranspiler::new(
-        transpiler_options,
-        &allocator,
-    );
-    transpiler.visit_program(&mut program);
-    let codegen = codegen::generate_code(&program);
-    println!("Transpiled Code:\n");
-    println!("{}", codegen);
-    // Write to `translated.js`
-    let output_path = Path::new("translated.js");
-    std::fs::write(output_path, codegen)
-        .unwrap_or_else(|err| panic!("Failed to write to {}: {err}", output_path.display()));
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use oxc::diagnostics::{Error, NamedSource, OxcDiagnostic};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use systemjs_oxc::{transpile, SystemGlobal, SystemJsTranspilerOptions};
+
+/// Extensions of the files picked up from input directories.
+const EXTENSIONS: [&str; 2] = ["js", "mjs"];
+
+/// Transpiles ES modules into `System.register` modules.
+///
+/// Without inputs (or with `-`), reads a module from stdin and writes the result to stdout.
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    after_help = "Exit codes: 0 on success, 1 if any input failed to transpile, 2 on invalid \
+                  usage or I/O errors."
+)]
+struct Cli {
+    /// Files or directories to transpile; directories are searched for .js and .mjs files.
+    inputs: Vec<PathBuf>,
+    /// Write every output into this directory, keeping the layout of input directories.
+    #[arg(short = 'd', long, conflicts_with = "out_file")]
+    out_dir: Option<PathBuf>,
+    /// Concatenate every output into this file; several inputs require --module-ids.
+    #[arg(short = 'o', long)]
+    out_file: Option<PathBuf>,
+    /// Path of the module read from stdin, used for its module id and diagnostics.
+    #[arg(long, default_value = "stdin.js")]
+    filename: PathBuf,
+    /// Register modules under a name computed from their path.
+    #[arg(long)]
+    module_ids: bool,
+    /// Register the module under this name; only allowed with a single input.
+    #[arg(long)]
+    module_id: Option<String>,
+    /// Directory module ids are computed relative to.
+    #[arg(long)]
+    module_root: Option<String>,
+    /// Keep top-level `this` instead of replacing it with `void 0`.
+    #[arg(long)]
+    allow_top_level_this: bool,
+    /// Object whose `register` method is called, e.g. `System` or `window.loaders.app`.
+    #[arg(long, default_value = "System")]
+    system_global: SystemGlobal,
+    /// Only report errors.
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+impl Cli {
+    fn options(&self) -> SystemJsTranspilerOptions {
+        SystemJsTranspilerOptions {
+            module_ids: self.module_ids,
+            module_id: self.module_id.clone().unwrap_or_default(),
+            get_module_id: None,
+            module_root: self.module_root.clone().unwrap_or_default(),
+            allow_top_level_this: self.allow_top_level_this,
+            system_global: self.system_global.clone(),
+        }
+    }
+
+    /// Transpiles `source_text`, reporting its diagnostics. Returns `None` if it failed.
+    fn transpile(
+        &self,
+        options: &SystemJsTranspilerOptions,
+        source_text: &str,
+        path: &Path,
+    ) -> Option<String> {
+        let report = |diagnostic: OxcDiagnostic| {
+            let source = NamedSource::new(path.to_string_lossy(), source_text.to_string());
+            eprintln!("{:?}", Error::from(diagnostic).with_source_code(source));
+        };
+        match transpile(source_text, path, options.clone()) {
+            Ok(output) => {
+                if !self.quiet {
+                    output.diagnostics.into_iter().for_each(report);
+                }
+                Some(output.code)
+            }
+            Err(errors) => {
+                errors.into_iter().map(OxcDiagnostic::from).for_each(report);
+                None
+            }
+        }
+    }
+}
+
+/// An input file and where its output goes relative to `--out-dir`.
+struct Input {
+    path: PathBuf,
+    relative: PathBuf,
+}
+
+/// Expands directories into the module files they contain, in a stable order.
+fn collect_inputs(paths: &[PathBuf]) -> std::io::Result<Vec<Input>> {
+    let mut inputs = vec![];
+    for path in paths {
+        if path.is_dir() {
+            let mut files = vec![];
+            collect_directory(path, &mut files)?;
+            files.sort();
+            inputs.extend(files.into_iter().map(|file| Input {
+                relative: file.strip_prefix(path).unwrap_or(&file).to_path_buf(),
+                path: file,
+            }));
+        } else {
+            inputs.push(Input {
+                relative: PathBuf::from(path.file_name().unwrap_or(path.as_os_str())),
+                path: path.clone(),
+            });
+        }
+    }
+    Ok(inputs)
+}
+
+fn collect_directory(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_directory(&path, files)?;
+        } else if path
+            .extension()
+            .is_some_and(|extension| EXTENSIONS.iter().any(|ext| extension == *ext))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Reports a usage error in the format of clap's own errors and exits with code 2.
+fn usage_error(kind: ErrorKind, message: &str) -> ! {
+    Cli::command().error(kind, message).exit()
+}
+
+fn files(count: usize) -> String {
+    match count {
+        1 => "1 file".to_string(),
+        count => format!("{count} files"),
+    }
+}
+
+fn run(cli: &Cli) -> std::io::Result<ExitCode> {
+    let options = cli.options();
+    if cli.inputs.is_empty() || cli.inputs.iter().all(|input| input.as_os_str() == "-") {
+        if cli.out_dir.is_some() {
+            usage_error(
+                ErrorKind::ArgumentConflict,
+                "--out-dir cannot be used when reading from stdin; use --out-file instead",
+            );
+        }
+        let mut source_text = String::new();
+        std::io::stdin().read_to_string(&mut source_text)?;
+        let Some(code) = cli.transpile(&options, &source_text, &cli.filename) else {
+            return Ok(ExitCode::FAILURE);
+        };
+        match &cli.out_file {
+            Some(out_file) => std::fs::write(out_file, code)?,
+            None => write!(std::io::stdout(), "{code}")?,
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    let inputs = collect_inputs(&cli.inputs)?;
+    if cli.module_id.is_some() && inputs.len() != 1 {
+        usage_error(
+            ErrorKind::ArgumentConflict,
+            &format!("--module-id names a single module, but got {}", files(inputs.len())),
+        );
+    }
+    // SystemJS rejects more than one anonymous `System.register` call in a single file
+    if cli.out_dir.is_none() && inputs.len() > 1 && !cli.module_ids {
+        usage_error(
+            ErrorKind::MissingRequiredArgument,
+            &format!(
+                "{} are concatenated into one output, which requires --module-ids to name \
+                 their registrations; or use --out-dir",
+                files(inputs.len())
+            ),
+        );
+    }
+    if cli.out_dir.is_some() {
+        let mut sources = HashMap::new();
+        for input in &inputs {
+            if let Some(other) = sources.insert(&input.relative, &input.path) {
+                usage_error(
+                    ErrorKind::ValueValidation,
+                    &format!(
+                        "{} and {} would both be written to {} in --out-dir",
+                        other.display(),
+                        input.path.display(),
+                        input.relative.display()
+                    ),
+                );
+            }
+        }
+    }
+    let mut outputs = vec![];
+    let mut failed = 0;
+    for input in &inputs {
+        let source_text = std::fs::read_to_string(&input.path).map_err(|err| {
+            std::io::Error::new(err.kind(), format!("{}: {err}", input.path.display()))
+        })?;
+        let Some(code) = cli.transpile(&options, &source_text, &input.path) else {
+            failed += 1;
+            continue;
+        };
+        match &cli.out_dir {
+            Some(out_dir) => {
+                let out_path = out_dir.join(&input.relative);
+                if let Some(parent) = out_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&out_path, code)?;
+            }
+            None => outputs.push(code),
+        }
+    }
+    match (&cli.out_dir, &cli.out_file) {
+        // A bundle missing some of its modules is not written at all
+        (Some(_), _) => {}
+        (None, _) if failed > 0 => {}
+        (None, Some(out_file)) => std::fs::write(out_file, outputs.concat())?,
+        (None, None) => {
+            let mut stdout = std::io::stdout().lock();
+            for code in outputs {
+                write!(stdout, "{code}")?;
+            }
+        }
+    }
+    if failed > 0 {
+        eprintln!("Failed to transpile {failed} of {}.", files(inputs.len()));
+        return Ok(ExitCode::FAILURE);
+    }
+    if !cli.quiet && (cli.out_dir.is_some() || cli.out_file.is_some()) {
+        eprintln!("Successfully transpiled {}.", files(inputs.len()));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::from(2)
+        }
+    }
 }
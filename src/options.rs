@@ -1,9 +1,173 @@
-#[derive(Debug, Default)]
+use oxc::syntax::identifier::is_identifier_name;
+use oxc::syntax::keyword::is_reserved_keyword;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Computes the name a module is registered under from its source path and the default name
+/// (see [`SystemJsTranspilerOptions::module_name`]); `None` registers it anonymously. Shared,
+/// so that the options can be cloned for every module and sent to other threads.
+pub type GetModuleId = Arc<dyn Fn(&Path, Option<&str>) -> Option<String> + Send + Sync>;
+
+#[derive(Clone, Default)]
 pub struct SystemJsTranspilerOptions {
     pub module_ids: bool,
     pub module_id: String,
-    // get_module_id:
+    pub get_module_id: Option<GetModuleId>,
     pub module_root: String,
     pub allow_top_level_this: bool,
-    pub system_global: String,
+    pub system_global: SystemGlobal,
 }
+
+impl fmt::Debug for SystemJsTranspilerOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SystemJsTranspilerOptions")
+            .field("module_ids", &self.module_ids)
+            .field("module_id", &self.module_id)
+            .field("get_module_id", &self.get_module_id.as_ref().map(|_| "Fn"))
+            .field("module_root", &self.module_root)
+            .field("allow_top_level_this", &self.allow_top_level_this)
+            .field("system_global", &self.system_global)
+            .finish()
+    }
+}
+
+impl SystemJsTranspilerOptions {
+    /// The name passed to `System.register`: `module_id` if set, otherwise, with `module_ids`
+    /// on, the source path relative to `module_root` without its extension. `get_module_id`, if
+    /// given, has the final say.
+    pub fn module_name(&self, source_path: &Path) -> Option<String> {
+        let default_name = self.default_module_name(source_path);
+        match &self.get_module_id {
+            Some(get_module_id) => get_module_id(source_path, default_name.as_deref()),
+            None => default_name,
+        }
+    }
+
+    fn default_module_name(&self, source_path: &Path) -> Option<String> {
+        if !self.module_id.is_empty() {
+            return Some(self.module_id.clone());
+        }
+        if !self.module_ids {
+            return None;
+        }
+        // Compare resolved paths, so that `./src/a.js` and `/abs/cwd/src/a.js` are both inside
+        // the module root `src`. Modules outside of it are named with `..` segments, like
+        // `../lib/a`, so that their names stay unique.
+        let root = resolve(Path::new(&self.module_root));
+        let path = resolve(&source_path.with_extension(""));
+        let common = root
+            .components()
+            .zip(path.components())
+            .take_while(|(root, path)| root == path)
+            .count();
+        let parents = root.components().count() - common;
+        let name = std::iter::repeat_n("..".into(), parents)
+            .chain(path.components().skip(common).filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy()),
+                _ => None,
+            }))
+            .collect::<Vec<_>>()
+            .join("/");
+        Some(name)
+    }
+}
+
+/// `path` resolved against the current directory, with its `.` and `..` segments removed,
+/// without touching the file system.
+fn resolve(path: &Path) -> PathBuf {
+    let path = if path.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        path
+    };
+    let mut resolved = PathBuf::new();
+    for component in std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()).components()
+    {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+/// The object whose `register` method the output calls: an identifier such as `System`, or a
+/// member path such as `window.__loaders.app`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemGlobal {
+    object: String,
+    properties: Vec<String>,
+}
+
+impl SystemGlobal {
+    /// The identifier the path starts from.
+    pub fn object(&self) -> &str {
+        &self.object
+    }
+
+    /// The property names that follow the identifier.
+    pub fn properties(&self) -> &[String] {
+        &self.properties
+    }
+}
+
+impl Default for SystemGlobal {
+    fn default() -> Self {
+        Self {
+            object: "System".to_string(),
+            properties: vec![],
+        }
+    }
+}
+
+impl FromStr for SystemGlobal {
+    type Err = InvalidSystemGlobal;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = s.split('.');
+        let object = segments.next().unwrap_or_default();
+        let properties: Vec<String> = segments.map(str::to_string).collect();
+        // Only the object is a binding; the properties may be reserved words.
+        if !is_identifier_name(object)
+            || is_reserved_keyword(object)
+            || !properties.iter().all(|property| is_identifier_name(property))
+        {
+            return Err(InvalidSystemGlobal(s.to_string()));
+        }
+        Ok(Self {
+            object: object.to_string(),
+            properties,
+        })
+    }
+}
+
+impl fmt::Display for SystemGlobal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.object)?;
+        for property in &self.properties {
+            write!(f, ".{property}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A `system_global` that is neither an identifier nor a dotted member path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidSystemGlobal(pub String);
+
+impl fmt::Display for InvalidSystemGlobal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` is not a valid system global, expected an identifier or a dotted member path",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidSystemGlobal {}
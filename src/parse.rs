@@ -1,9 +1,18 @@
 use oxc::allocator::Allocator;
 use oxc::ast::ast;
+use oxc::diagnostics::OxcDiagnostic;
 use oxc::parser::Parser;
 use oxc::span::SourceType;
 
-pub fn parse_program<'a>(source: &'a str, alloc: &'a Allocator) -> ast::Program<'a> {
+/// Parses `source` as an ES module, failing with every syntax error found.
+pub fn parse_program<'a>(
+    source: &'a str,
+    alloc: &'a Allocator,
+) -> Result<ast::Program<'a>, Vec<OxcDiagnostic>> {
     let parser = Parser::new(alloc, source, SourceType::mjs());
-    parser.parse().program
+    let ret = parser.parse();
+    if ret.panicked || !ret.errors.is_empty() {
+        return Err(ret.errors);
+    }
+    Ok(ret.program)
 }
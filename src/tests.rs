@@ -0,0 +1,328 @@
+use crate::{transpile, SystemJsTranspilerOptions, TranspileOutput};
+use std::path::Path;
+use std::sync::Arc;
+
+fn transpile_output(source_text: &str) -> TranspileOutput {
+    transpile(source_text, Path::new("test.js"), SystemJsTranspilerOptions::default())
+        .unwrap_or_else(|errors| panic!("failed to transpile: {errors:?}"))
+}
+
+fn transpile_code(source_text: &str) -> String {
+    transpile_output(source_text).code
+}
+
+/// Asserts that `code` contains every line of `expected`, ignoring indentation.
+#[track_caller]
+fn assert_lines(code: &str, expected: &[&str]) {
+    let lines: Vec<&str> = code.lines().map(str::trim).collect();
+    for line in expected {
+        assert!(lines.contains(line), "missing `{line}` in:\n{code}");
+    }
+}
+
+#[test]
+fn postfix_update_exports_the_updated_value() {
+    let code = transpile_code("export let c = 1;\nconst old = c++;\nc--;\n");
+    assert_lines(
+        &code,
+        &[
+            "old = (_c = c++, _export(\"c\", c), _c);",
+            "c--, _export(\"c\", c);",
+        ],
+    );
+}
+
+#[test]
+fn prefix_and_compound_updates_are_wrapped() {
+    let code = transpile_code("export let c = 1;\n++c;\nc += 2;\nc = 3;\n");
+    assert_lines(
+        &code,
+        &[
+            "_export(\"c\", ++c);",
+            "_export(\"c\", c += 2);",
+            "_export(\"c\", c = 3);",
+        ],
+    );
+}
+
+#[test]
+fn destructuring_assignment_keeps_its_value() {
+    let code = transpile_code("export let x, y;\nconst r = ([x, y] = [1, 2]);\n[x] = [3];\n");
+    assert_lines(
+        &code,
+        &[
+            "r = (_ref = [1, 2], [x, y] = _ref, _export(\"x\", x), _export(\"y\", y), _ref);",
+            "[x] = [3], _export(\"x\", x);",
+        ],
+    );
+}
+
+#[test]
+fn parenthesized_destructuring_discards_its_value() {
+    let code = transpile_code("export let x;\n({ x } = {});\n");
+    assert_lines(&code, &["({x} = {}), _export(\"x\", x);"]);
+    assert!(!code.contains("_ref"), "unneeded temporary in:\n{code}");
+}
+
+#[test]
+fn temporaries_are_declared_in_the_nearest_function() {
+    let code = transpile_code(
+        "export let n = 0;\nexport function dec() { return n--; }\nexport const inc = () => n++;\n",
+    );
+    assert_lines(
+        &code,
+        &[
+            "var n, inc;",
+            "function dec() {",
+            "var _n;",
+            "return _n = n--, _export(\"n\", n), _n;",
+            "_export(\"inc\", inc = () => {",
+            "var _n2;",
+            "return _n2 = n++, _export(\"n\", n), _n2;",
+        ],
+    );
+}
+
+#[test]
+fn shadowed_bindings_are_not_exported() {
+    let code = transpile_code("export let c = 1;\nfunction f(c) { c++; }\n");
+    assert_lines(&code, &["c++;"]);
+}
+
+#[test]
+fn setters_export_reexported_imports() {
+    let code =
+        transpile_code("import { a } from \"x\";\nimport b from \"y\";\nexport { a, b as c };\n");
+    assert_lines(
+        &code,
+        &["_export(\"a\", a = _x.a);", "_export(\"c\", b = _y.default);"],
+    );
+}
+
+#[test]
+fn loop_heads_export_their_targets() {
+    let code = transpile_code("export let k;\nfor (k of [1, 2]) {}\nfor (k in o) f(k);\n");
+    assert_eq!(code.matches("_export(\"k\", k);").count(), 2, "{code}");
+}
+
+#[test]
+fn star_exports_skip_names_exported_explicitly() {
+    let code = transpile_code("export * from \"a\";\nexport const x = 1;\n");
+    assert_lines(
+        &code,
+        &[
+            "var _exportNames = { x: true };",
+            concat!(
+                "if (_key !== \"default\" && _key !== \"__esModule\" && ",
+                "!Object.prototype.hasOwnProperty.call(_exportNames, _key)) ",
+                "_exportObj[_key] = _a[_key];",
+            ),
+        ],
+    );
+}
+
+#[test]
+fn star_exports_reset_ambiguous_names() {
+    let code = transpile_code("export * from \"a\";\nexport * from \"b\";\n");
+    assert_lines(
+        &code,
+        &[
+            "_exportStar = _a;",
+            concat!(
+                "if (_key !== \"default\" && _key !== \"__esModule\") _exportObj[_key] = ",
+                "_exportStar2 && _key in _exportStar2 && _exportStar2[_key] !== _a[_key] ",
+                "? void 0 : _a[_key];",
+            ),
+            "_exportStar2 = _b;",
+            concat!(
+                "if (_key !== \"default\" && _key !== \"__esModule\") _exportObj[_key] = ",
+                "_exportStar && _key in _exportStar && _exportStar[_key] !== _b[_key] ",
+                "? void 0 : _b[_key];",
+            ),
+        ],
+    );
+}
+
+#[test]
+fn string_export_names_are_exported() {
+    let code = transpile_code("function f() {}\nexport { f as \"a-b\", f as g };\n");
+    assert_lines(&code, &["\"a-b\": f,", "g: f"]);
+}
+
+#[test]
+fn module_names_are_relative_to_the_module_root() {
+    let options = SystemJsTranspilerOptions {
+        module_ids: true,
+        module_root: "src".to_string(),
+        ..SystemJsTranspilerOptions::default()
+    };
+    assert_eq!(options.module_name(Path::new("./src/lib/a.js")).as_deref(), Some("lib/a"));
+    let inside = std::env::current_dir().unwrap().join("src/b.mjs");
+    assert_eq!(options.module_name(&inside).as_deref(), Some("b"));
+    assert_eq!(options.module_name(Path::new("./a.js")).as_deref(), Some("../a"));
+    assert_eq!(options.module_name(Path::new("src/../lib/a.js")).as_deref(), Some("../lib/a"));
+    let options = SystemJsTranspilerOptions {
+        module_root: "/r/src".to_string(),
+        ..options
+    };
+    assert_eq!(options.module_name(Path::new("/r/src/a.js")).as_deref(), Some("a"));
+    assert_eq!(options.module_name(Path::new("/r/a.js")).as_deref(), Some("../a"));
+    assert_eq!(options.module_name(Path::new("/r/other/a.js")).as_deref(), Some("../other/a"));
+    assert_eq!(options.module_name(Path::new("/tmp/a.js")).as_deref(), Some("../../tmp/a"));
+}
+
+#[test]
+fn options_can_be_shared_between_threads() {
+    let options = SystemJsTranspilerOptions {
+        get_module_id: Some(Arc::new(|path, _| Some(path.display().to_string()))),
+        ..SystemJsTranspilerOptions::default()
+    };
+    let handle = std::thread::spawn({
+        let options = options.clone();
+        move || options.module_name(Path::new("a.js"))
+    });
+    assert_eq!(handle.join().unwrap().as_deref(), Some("a.js"));
+    assert_eq!(options.module_name(Path::new("b.js")).as_deref(), Some("b.js"));
+}
+
+#[test]
+fn transformer_warnings_are_not_fatal() {
+    let output = transpile_output("export let x = 0n;\n");
+    assert_lines(&output.code, &["_export(\"x\", x = 0n);"]);
+    assert!(!output.diagnostics.is_empty());
+}
+
+#[test]
+fn top_level_await_runs_in_a_generator() {
+    let output = transpile_output("export const a = await load();\nfor await (const b of a) {}\n");
+    assert!(output.has_top_level_await);
+    assert!(output
+        .dependencies
+        .contains(&"@oxc-project/runtime/helpers/asyncToGenerator".to_string()));
+    assert_lines(
+        &output.code,
+        &[
+            "execute: function() {",
+            "return _asyncToGenerator(function* () {",
+            "_export(\"a\", a = yield load());",
+            "for (_iterator = _asyncIterator(a); _iteratorAbruptCompletion = !(_step = yield \
+             _iterator.next()).done; _iteratorAbruptCompletion = false) {",
+            "}).call(this);",
+        ],
+    );
+}
+
+#[test]
+fn await_in_functions_is_not_top_level() {
+    let output = transpile_output("export async function f() { await g(); }\n");
+    assert!(!output.has_top_level_await);
+    assert!(!output.code.contains("}).call(this);"), "async execute in:\n{}", output.code);
+}
+
+#[test]
+fn binding_patterns_become_assignment_targets() {
+    let code = transpile_code(concat!(
+        "const { a, b: { c = 1 }, [k()]: e = 2 } = o;\n",
+        "let [g, [h = 3], , ...i] = arr, { j: [l] = [] } = o;\n",
+    ));
+    assert_lines(
+        &code,
+        &[
+            "({a, b: {c = 1}, [k()]: e = 2} = o);",
+            "[g, [h = 3], , ...i] = arr, {j: [l] = []} = o;",
+        ],
+    );
+}
+
+#[test]
+fn declarations_without_initializers() {
+    let code = transpile_code("let x;\nvar y;\nlet z = 1, w;\n");
+    assert_lines(&code, &["var x, y, z, w;", "x = void 0;", "z = 1, w = void 0;"]);
+    assert!(!code.contains("y = "), "`var y;` was assigned in:\n{code}");
+}
+
+#[test]
+fn nested_module_vars_are_hoisted_once() {
+    let code = transpile_code(concat!(
+        "export var v; var v = 2;\n",
+        "if (c) { var a = 1; }\n",
+        "for (var i = 0; i < 2; i++) { var j; }\n",
+        "for (var k in o) {}\n",
+        "try { var t = 1; } catch (e) { var u = e; }\n",
+        "outer: { var l = 1; break outer; }\n",
+    ));
+    assert_lines(
+        &code,
+        &[
+            "var v, a, j, i, k, t, u, l;",
+            "_export(\"v\", v = 2);",
+            "a = 1;",
+            "for (i = 0; i < 2; i++) {",
+            "for (k in o) {}",
+            "t = 1;",
+            "u = e;",
+            "l = 1;",
+        ],
+    );
+}
+
+#[test]
+fn top_level_this_is_undefined() {
+    let output = transpile_output(concat!(
+        "const f = async () => this;\n",
+        "const g = () => this.x;\n",
+        "function h() { return this.h; }\n",
+        "const o = { m() { return this.m; } };\n",
+        "class A { a = this; static s = this.s; static { this.t = 1; } }\n",
+    ));
+    assert_lines(
+        &output.code,
+        &[
+            "_this = void 0;",
+            "g = () => (void 0).x;",
+            "return this.h;",
+            "return this.m;",
+            "_defineProperty(this, \"a\", this);",
+            "_defineProperty(A, \"s\", _A.s);",
+            "_A.t = 1;",
+        ],
+    );
+    let labels: Vec<_> = output
+        .diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.labels.as_ref().map(Vec::len))
+        .collect();
+    // The `this` of the async arrow is captured by lowered code, which has no position
+    assert_eq!(labels, [None, Some(1)]);
+}
+
+#[test]
+fn top_level_this_can_be_kept() {
+    let options = SystemJsTranspilerOptions {
+        allow_top_level_this: true,
+        ..SystemJsTranspilerOptions::default()
+    };
+    let output = transpile("this.x = 1;\n", Path::new("test.js"), options)
+        .unwrap_or_else(|errors| panic!("failed to transpile: {errors:?}"));
+    assert_lines(&output.code, &["this.x = 1;"]);
+    assert!(output.diagnostics.is_empty());
+}
+
+#[test]
+fn default_exports_are_listed() {
+    let output = transpile_output("export default 42;\nexport const a = 1;\n");
+    assert_eq!(output.exports, ["default", "a"]);
+    let output = transpile_output("export default function f() {}\n");
+    assert_eq!(output.exports, ["default"]);
+    let output = transpile_output("const b = 1;\nexport { b as default };\n");
+    assert_eq!(output.exports, ["default"]);
+}
+
+#[test]
+fn semantic_errors_are_fatal() {
+    for source_text in ["export { nope };\n", "let a;\nvar a;\n"] {
+        let options = SystemJsTranspilerOptions::default();
+        let result = transpile(source_text, Path::new("test.js"), options);
+        assert!(result.is_err(), "`{source_text}` was transpiled");
+    }
+}
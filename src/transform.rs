@@ -1,24 +1,44 @@
 use oxc::allocator::Allocator;
 use oxc::ast::ast;
+use oxc::diagnostics::{OxcDiagnostic, Severity};
 use oxc::semantic::{Scoping, SemanticBuilder};
 use oxc::transformer::{EnvOptions, TransformOptions, Transformer};
 use std::path::Path;
 
+/// Lowers `program` to ES2015. Returns the scoping of the lowered program along with the
+/// warnings of the transformer, or fails with the semantic errors of `program` or the errors of
+/// the transformer.
 pub fn transform_to_es5<'a>(
     program: &mut ast::Program<'a>,
     allocator: &'a Allocator,
     source_path: &Path,
-) -> Scoping {
+) -> Result<(Scoping, Vec<OxcDiagnostic>), Vec<OxcDiagnostic>> {
     let ret = SemanticBuilder::new()
+        // Early errors the parser leaves to semantic analysis, such as `export { undeclared }`
+        .with_check_syntax_error(true)
         // Estimate transformer will triple scopes, symbols, references
         .with_excess_capacity(2.0)
-        .build(&program);
-    let env = EnvOptions::from_target("es2015").expect("Should be able to create EnvOptions for ES5");
+        .build(program);
+    if !ret.errors.is_empty() {
+        return Err(ret.errors);
+    }
     let options = TransformOptions {
-        env,
+        env: env_options(),
         ..TransformOptions::default()
     };
     let transformer = Transformer::new(allocator, source_path, &options);
     let transformed = transformer.build_with_scoping(ret.semantic.into_scoping(), program);
-    transformed.scoping
+    let (errors, warnings): (Vec<_>, Vec<_>) = transformed
+        .errors
+        .into_iter()
+        .partition(|diagnostic| diagnostic.severity == Severity::Error);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok((transformed.scoping, warnings))
+}
+
+/// The environment the module is lowered to before it is wrapped in `System.register`.
+pub fn env_options() -> EnvOptions {
+    EnvOptions::from_target("es2015").expect("Should be able to create EnvOptions for ES5")
 }
@@ -1,31 +1,86 @@
+use crate::error::TranspileError;
 use crate::options::SystemJsTranspilerOptions;
+use crate::visit::UsedNames;
 use oxc::allocator::{Allocator, Box as ArenaBox, CloneIn, Vec as ArenaVec};
 use oxc::ast::ast::{
-    BindingIdentifier, ExportAllDeclaration, Function,
+    BindingIdentifier, ExportAllDeclaration, ExportNamedDeclaration, Function,
     ImportDeclaration, ObjectPropertyKind,
     StringLiteral,
 };
-use oxc::ast::{AstBuilder, AstType};
-use oxc::semantic::{ScopeFlags};
+use oxc::ast::AstBuilder;
+use oxc::diagnostics::OxcDiagnostic;
+use oxc::semantic::{ScopeFlags, Scoping};
+use oxc::span::Atom;
+use std::collections::HashMap;
+use std::path::Path;
 
 pub struct SystemJsTranspiler<'a> {
     pub options: SystemJsTranspilerOptions,
+    /// Name the module is registered under, or `None` for an anonymous registration.
+    pub module_name: Option<String>,
     pub allocator: &'a Allocator,
+    /// Semantic scoping of the (already transformed) program, used to resolve bindings.
+    pub scoping: Scoping,
     pub scope_stack: Vec<ScopeFlags>,
-    pub path_stack: Vec<AstType>,
+    /// How many class field initializers we are in, where `this` is the instance or class.
+    pub class_field_depth: usize,
+    /// Set right before visiting an expression whose value is thrown away, such as that of an
+    /// expression statement, so that rewrites need not preserve it.
+    pub value_discarded: bool,
     pub builder: AstBuilder<'a>,
+    /// Temporaries of every function (or static block) we are in, declared at the start of its
+    /// body. Temporaries outside of any function are hoisted with `top_level_vars` instead.
+    pub function_temporaries: Vec<Vec<BindingIdentifier<'a>>>,
     pub top_level_vars: Vec<BindingIdentifier<'a>>,
     pub top_level_function_decls: ArenaVec<'a, Function<'a>>,
     pub top_level_classes: Vec<BindingIdentifier<'a>>,
     pub imported_symbols: Vec<BindingIdentifier<'a>>,
-    pub imports: ArenaVec<'a, ImportMap<'a>>,
-    pub importee: ArenaVec<'a, StringLiteral<'a>>,
+    pub dependencies: ArenaVec<'a, Dependency<'a>>,
     pub export_tree: ArenaVec<'a, ObjectPropertyKind<'a>>,
+    /// Local bindings of the module mapped to the names they are exported as.
+    pub exported_bindings: HashMap<Atom<'a>, Vec<Atom<'a>>>,
+    /// Every name the module exports explicitly, which `export *` must not override.
+    pub explicit_exports: Vec<Atom<'a>>,
+    /// The hoisted `_exportNames` object, present when the module has both `export *` and
+    /// explicit exports.
+    pub export_names_object: Option<Atom<'a>>,
+    /// Hoisted holders for the namespaces of each `export *` source, used to detect ambiguous
+    /// names when there is more than one of them.
+    pub star_export_namespaces: Vec<Atom<'a>>,
+    /// Whether the module body uses `await` (or `for await`) outside of any function.
+    pub has_top_level_await: bool,
+    /// Identifier names already in use, including the ones we generated.
+    pub used_names: UsedNames<'a>,
+    /// Name of the `_export` parameter of the declaration function.
+    pub export_name: Atom<'a>,
+    /// Name of the `_context` parameter of the declaration function.
+    pub context_name: Atom<'a>,
+    /// Name of the object `export *` setters collect the re-exported names into.
+    pub export_object_name: Atom<'a>,
+    /// Name of the loop variable `export *` setters iterate the namespace with.
+    pub export_key_name: Atom<'a>,
+    /// Warnings about code whose behaviour changes once it runs inside `System.register`.
+    pub diagnostics: Vec<OxcDiagnostic>,
+    /// Constructs that could not be converted, reported to the caller instead of the output.
+    pub errors: Vec<TranspileError>,
 }
 
+/// A module this module depends on, together with every statement that imports from or
+/// re-exports it. Each dependency is listed once and gets a single setter.
 #[derive(Debug)]
+pub struct Dependency<'a> {
+    pub source: StringLiteral<'a>,
+    /// Parameter of the setter that receives the namespace of the dependency.
+    pub namespace: Atom<'a>,
+    pub entries: ArenaVec<'a, ImportMap<'a>>,
+}
+
+/// Module-level statements that depend on another module, named after the AST nodes they wrap.
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ImportMap<'a> {
     ImportDeclaration(ArenaBox<'a, ImportDeclaration<'a>>),
+    ExportNamedDeclaration(ArenaBox<'a, ExportNamedDeclaration<'a>>),
     ExportAllDeclaration(ArenaBox<'a, ExportAllDeclaration<'a>>),
 }
 
@@ -36,6 +91,9 @@ impl<'a> CloneIn<'a> for ImportMap<'a> {
             ImportMap::ImportDeclaration(it) => {
                 ImportMap::ImportDeclaration(it.clone_in(allocator))
             }
+            ImportMap::ExportNamedDeclaration(it) => {
+                ImportMap::ExportNamedDeclaration(it.clone_in(allocator))
+            }
             ImportMap::ExportAllDeclaration(it) => {
                 ImportMap::ExportAllDeclaration(it.clone_in(allocator))
             }
@@ -47,21 +105,39 @@ impl<'a> SystemJsTranspiler<'a> {
     pub fn new(
         options: SystemJsTranspilerOptions,
         allocator: &'a Allocator,
+        scoping: Scoping,
+        source_path: &Path,
     ) -> Self {
         let builder = AstBuilder::new(allocator);
+        let used_names = UsedNames::from_scoping(&scoping, allocator);
         Self {
+            module_name: options.module_name(source_path),
             options,
             allocator,
+            scoping,
             scope_stack: vec![],
-            path_stack: vec![],
+            class_field_depth: 0,
+            value_discarded: false,
             top_level_function_decls: builder.vec(),
             builder,
+            function_temporaries: vec![],
             top_level_vars: vec![],
             top_level_classes: vec![],
             imported_symbols: vec![],
-            imports: builder.vec(),
-            importee: builder.vec(),
+            dependencies: builder.vec(),
             export_tree: builder.vec(),
+            exported_bindings: HashMap::new(),
+            explicit_exports: vec![],
+            export_names_object: None,
+            star_export_namespaces: vec![],
+            has_top_level_await: false,
+            used_names,
+            export_name: Atom::from("_export"),
+            context_name: Atom::from("_context"),
+            export_object_name: Atom::from("_exportObj"),
+            export_key_name: Atom::from("_key"),
+            diagnostics: vec![],
+            errors: vec![],
         }
     }
 }
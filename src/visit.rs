@@ -1,23 +1,29 @@
-use crate::transpiler::{ImportMap, SystemJsTranspiler};
-use oxc::allocator::Vec as ArenaVec;
-use oxc::allocator::{CloneIn, FromIn, TakeIn};
+use crate::error::TranspileError;
+use crate::transform;
+use crate::transpiler::{Dependency, ImportMap, SystemJsTranspiler};
+use oxc::allocator::{Allocator, Box as ArenaBox, CloneIn, FromIn, TakeIn, Vec as ArenaVec};
 use oxc::ast::ast::{
     Argument, ArrayExpressionElement, AssignmentOperator, AssignmentTarget,
-    AssignmentTargetMaybeDefault, BindingIdentifier, ClassType, Declaration, ExportAllDeclaration,
-    ExportDefaultDeclarationKind, Expression, FormalParameterKind, FunctionType,
-    IdentifierReference, ImportDeclaration, ImportDeclarationSpecifier, Program, PropertyKind,
-    Statement, VariableDeclarationKind,
+    AssignmentTargetMaybeDefault, BinaryOperator, BindingIdentifier, BindingPattern,
+    BindingPatternKind, ClassType, Declaration, ExportAllDeclaration, ExportDefaultDeclarationKind,
+    ExportNamedDeclaration, Expression, FormalParameterKind, FunctionType, IdentifierReference,
+    ImportDeclaration, ImportDeclarationSpecifier, ImportOrExportKind, LogicalOperator, Program,
+    PropertyKind, Statement, StringLiteral, UnaryOperator,
+    VariableDeclarationKind,
 };
-use oxc::ast::{ast, match_declaration, AstType, NONE};
+use oxc::ast::{ast, match_declaration, NONE};
 use oxc::ast_visit::{walk_mut, Visit, VisitMut};
-use oxc::codegen::Codegen;
-use oxc::parser::Parser;
-use oxc::semantic::{ScopeFlags, ScopeId};
-use oxc::span::{Atom, SourceType, SPAN};
+use oxc::diagnostics::OxcDiagnostic;
+use oxc::semantic::{ScopeFlags, ScopeId, Scoping};
+use oxc::span::{Atom, SPAN};
 use oxc::syntax::identifier;
 use oxc::syntax::identifier::is_identifier_name;
 use std::borrow::Cow;
 use std::cell::Cell;
+use std::collections::HashSet;
+
+/// Runtime helper the transformer uses to down-level async functions.
+const ASYNC_TO_GENERATOR: &str = "@oxc-project/runtime/helpers/asyncToGenerator";
 
 impl<'a> Visit<'a> for SystemJsTranspiler<'a> {}
 
@@ -30,54 +36,45 @@ impl<'a> VisitMut<'a> for SystemJsTranspiler<'a> {
         self.scope_stack.pop();
     }
 
-    fn enter_node(&mut self, kind: AstType) {
-        self.path_stack.push(kind);
-    }
-
-    fn leave_node(&mut self, _kind: AstType) {
-        self.path_stack.pop();
-    }
-
     fn visit_program(&mut self, it: &mut Program<'a>) {
+        self.export_name = self.used_names.unused_name("_export", self.allocator);
+        self.context_name = self.used_names.unused_name("_context", self.allocator);
+        self.collect_exports(it);
         walk_mut::walk_program(self, it);
+        let async_to_generator = (self.has_top_level_await
+            && transform::env_options().es2017.async_to_generator)
+            .then(|| self.async_to_generator_helper());
         // imported_symbols + top_level_classes + top_level_vars
         let mut decl_targets = vec![];
-        decl_targets.extend(std::mem::replace(&mut self.imported_symbols, vec![]));
-        decl_targets.extend(std::mem::replace(&mut self.top_level_classes, vec![]));
-        decl_targets.extend(std::mem::replace(&mut self.top_level_vars, vec![]));
-        let targets = self.builder.vec_from_iter(decl_targets.iter().map(|var| {
-            self.builder.variable_declarator(
-                SPAN,
-                VariableDeclarationKind::Var,
-                self.builder.binding_pattern(
-                    self.builder
-                        .binding_pattern_kind_binding_identifier(SPAN, var.name),
-                    NONE,
-                    false,
-                ),
-                None,
-                false,
-            )
-        }));
-        let declaration =
-            self.builder
-                .variable_declaration(SPAN, VariableDeclarationKind::Var, targets, false);
+        decl_targets.extend(std::mem::take(&mut self.imported_symbols));
+        decl_targets.extend(std::mem::take(&mut self.top_level_classes));
+        decl_targets.extend(std::mem::take(&mut self.top_level_vars));
+        decl_targets.extend(
+            self.star_export_namespaces
+                .iter()
+                .map(|name| self.builder.binding_identifier(SPAN, *name)),
+        );
+        // `var v; var v = 2;` declares `v` twice, but it is a single module binding
+        let mut declared = HashSet::new();
+        decl_targets.retain(|binding| declared.insert(binding.name));
         let mut body = self
             .builder
             .vec_from_iter(self.top_level_function_decls.iter_mut().map(|x| {
                 Statement::FunctionDeclaration(self.builder.alloc(x.take_in(self.allocator)))
             }));
-        body.insert(
-            0,
-            Statement::VariableDeclaration(self.builder.alloc(declaration)),
-        );
+        if let Some(export_names) = self.export_names_object {
+            body.insert(0, self.build_export_names_declaration(export_names));
+        }
+        if !decl_targets.is_empty() {
+            body.insert(0, self.var_statement(&decl_targets));
+        }
         if !self.export_tree.is_empty() {
             body.push(
                 self.builder.statement_expression(
                     SPAN,
                     self.builder.expression_call(
                         SPAN,
-                        self.builder.expression_identifier(SPAN, "_exports"),
+                        self.export_function(),
                         NONE,
                         self.builder.vec1(Argument::from(
                             self.builder
@@ -88,6 +85,12 @@ impl<'a> VisitMut<'a> for SystemJsTranspiler<'a> {
                 ),
             );
         }
+        let dependency_sources =
+            self.builder.vec_from_iter(self.dependencies.iter().map(|dependency| {
+                ArrayExpressionElement::StringLiteral(
+                    self.builder.alloc(dependency.source.clone_in(self.allocator)),
+                )
+            }));
         let mut return_object_items = self.builder.vec();
         return_object_items.push(
             self.builder.object_property_kind_object_property(
@@ -97,7 +100,7 @@ impl<'a> VisitMut<'a> for SystemJsTranspiler<'a> {
                 self.builder.expression_array(
                     SPAN,
                     self.builder.vec_from_iter(
-                        self.convert_import_map().iter_mut().map(|import| {
+                        self.convert_dependencies().iter_mut().map(|import| {
                             ArrayExpressionElement::from(import.take_in(self.builder))
                         }),
                     ),
@@ -119,27 +122,7 @@ impl<'a> VisitMut<'a> for SystemJsTranspiler<'a> {
                 SPAN,
                 PropertyKind::Init,
                 self.builder.property_key_static_identifier(SPAN, "execute"),
-                self.builder.expression_function(
-                    SPAN,
-                    FunctionType::FunctionExpression,
-                    None,
-                    false,
-                    false,
-                    false,
-                    NONE,
-                    NONE,
-                    self.builder.formal_parameters(
-                        SPAN,
-                        FormalParameterKind::FormalParameter,
-                        self.builder.vec(),
-                        NONE,
-                    ),
-                    NONE,
-                    Some(
-                        self.builder
-                            .function_body(SPAN, self.builder.vec(), new_body),
-                    ),
-                ),
+                self.build_execute_function(new_body, async_to_generator),
                 false,
                 false,
                 false,
@@ -150,6 +133,10 @@ impl<'a> VisitMut<'a> for SystemJsTranspiler<'a> {
             Some(self.builder.expression_object(SPAN, return_object_items)),
         );
         body.push(return_factory);
+        let factory_params = self.builder.vec_from_array([
+            self.formal_parameter(self.export_name),
+            self.formal_parameter(self.context_name),
+        ]);
         let factory_function =
             self.builder.expression_function(
                 SPAN,
@@ -163,7 +150,7 @@ impl<'a> VisitMut<'a> for SystemJsTranspiler<'a> {
                 self.builder.formal_parameters(
                     SPAN,
                     FormalParameterKind::FormalParameter,
-                    self.builder.vec(),
+                    factory_params,
                     NONE,
                 ),
                 NONE,
@@ -182,15 +169,17 @@ impl<'a> VisitMut<'a> for SystemJsTranspiler<'a> {
                 )),
             );
         let mut params = self.builder.vec();
+        if let Some(module_name) = &self.module_name {
+            params.push(Argument::from(self.builder.expression_string_literal(
+                SPAN,
+                self.builder.atom(module_name),
+                None,
+            )));
+        }
         params.push(Argument::from(
             self.builder.expression_array(
                 SPAN,
-                self.builder.vec_from_iter(
-                    self.importee
-                        .take_in(self.allocator)
-                        .into_iter()
-                        .map(|lit| ArrayExpressionElement::StringLiteral(self.builder.alloc(lit))),
-                ),
+                dependency_sources,
             ),
         ));
         params.push(Argument::from(factory_function));
@@ -198,13 +187,11 @@ impl<'a> VisitMut<'a> for SystemJsTranspiler<'a> {
             SPAN,
             self.builder.expression_call(
                 SPAN,
-                Expression::StaticMemberExpression(self.builder.alloc(
-                    self.builder.static_member_expression(
-                        SPAN,
-                        self.builder.expression_identifier(SPAN, "System"),
-                        self.builder.identifier_name(SPAN, "register"),
-                        false,
-                    ),
+                Expression::from(self.builder.member_expression_static(
+                    SPAN,
+                    self.system_global(),
+                    self.builder.identifier_name(SPAN, "register"),
+                    false,
                 )),
                 NONE,
                 params,
@@ -215,26 +202,65 @@ impl<'a> VisitMut<'a> for SystemJsTranspiler<'a> {
     }
 
     fn visit_expression(&mut self, expr: &mut Expression<'a>) {
+        let keep_value = !std::mem::take(&mut self.value_discarded);
+        // The value of `(expr)` is that of `expr`
+        if !keep_value && matches!(expr, Expression::ParenthesizedExpression(_)) {
+            self.value_discarded = true;
+        }
         match expr {
-            Expression::ThisExpression(_)
-                if !self.options.allow_top_level_this && self.is_valid_tla_scope() =>
+            Expression::ThisExpression(this)
+                if !self.options.allow_top_level_this && self.is_module_this() =>
             {
-                *expr = self.builder.void_0(SPAN)
+                let mut diagnostic =
+                    OxcDiagnostic::warn("Top-level `this` is `undefined` in a module")
+                        .with_help("It was replaced with `void 0`.");
+                // `this` captured by lowered code (e.g. `_this = this` for async arrows) has no
+                // position in the source
+                if !this.span.is_empty() {
+                    diagnostic = diagnostic.with_label(this.span);
+                }
+                self.diagnostics.push(diagnostic);
+                *expr = self.builder.void_0(this.span)
             }
             _ => {}
         }
         walk_mut::walk_expression(self, expr);
+        match expr {
+            Expression::AssignmentExpression(_) | Expression::UpdateExpression(_) => {
+                *expr = self.export_assignment(expr.take_in(self.allocator), keep_value);
+            }
+            Expression::AwaitExpression(await_expr) if self.is_module_scope_code() => {
+                self.has_top_level_await = true;
+                if transform::env_options().es2017.async_to_generator {
+                    let argument = await_expr.argument.take_in(self.allocator);
+                    *expr = self.builder.expression_yield(await_expr.span, false, Some(argument));
+                }
+            }
+            Expression::ImportExpression(import) if import.phase.is_some() => {
+                self.errors.push(TranspileError::unsupported(
+                    "Import phases (`import.source()`, `import.defer()`) are not supported by \
+                     SystemJS",
+                    import.span,
+                ));
+            }
+            Expression::ImportExpression(import) => {
+                *expr = self.convert_dynamic_import(import);
+            }
+            Expression::MetaProperty(meta)
+                if meta.meta.name == "import" && meta.property.name == "meta" =>
+            {
+                *expr = self.context_member("meta");
+            }
+            _ => {}
+        }
     }
 
     fn visit_statement(&mut self, it: &mut Statement<'a>) {
         walk_mut::walk_statement(self, it);
         match it {
-            decl @ match_declaration!(Statement) => {
-                let declaration = decl.clone_in(self.allocator).into_declaration();
-                let converted = self.convert_declaration(declaration);
-                if let Some(new_code) = converted {
-                    *decl = self.builder.statement_expression(SPAN, new_code)
-                }
+            match_declaration!(Statement) => {
+                let declaration = it.take_in(self.allocator).into_declaration();
+                *it = self.convert_declaration(declaration);
             }
             Statement::ImportDeclaration(_) => *it = self.builder.statement_empty(SPAN),
             Statement::ExportAllDeclaration(_) => *it = self.builder.statement_empty(SPAN),
@@ -242,294 +268,250 @@ impl<'a> VisitMut<'a> for SystemJsTranspiler<'a> {
                 if let Some(decl) = export.declaration.take() {
                     match &decl {
                         Declaration::FunctionDeclaration(func) => {
-                            let fn_name = func
-                                .id
-                                .clone_in(self.allocator)
-                                .expect("Should have a name")
-                                .name;
-                            self.export_tree.push(
-                                self.builder.object_property_kind_object_property(
-                                    SPAN,
-                                    PropertyKind::Init,
-                                    self.builder.property_key_static_identifier(
+                            // `export function` always has a name.
+                            if let Some(fn_name) = func.id.as_ref().map(|id| id.name) {
+                                self.export_tree.push(
+                                    self.builder.object_property_kind_object_property(
                                         SPAN,
-                                        fn_name.clone_in(self.allocator),
+                                        PropertyKind::Init,
+                                        self.builder.property_key_static_identifier(
+                                            SPAN,
+                                            fn_name.clone_in(self.allocator),
+                                        ),
+                                        self.builder.expression_identifier(SPAN, fn_name),
+                                        false,
+                                        false,
+                                        false,
                                     ),
-                                    self.builder.expression_identifier(SPAN, fn_name),
-                                    false,
-                                    false,
-                                    false,
-                                ),
-                            );
+                                );
+                            }
                         }
                         Declaration::ClassDeclaration(cls) => {
-                            let class_name = cls
-                                .id
-                                .clone_in(self.allocator)
-                                .expect("Should have a name")
-                                .name;
-                            self.export_tree.push(
-                                self.builder.object_property_kind_object_property(
-                                    SPAN,
-                                    PropertyKind::Init,
-                                    self.builder.property_key_static_identifier(
-                                        SPAN,
-                                        class_name.clone_in(self.allocator),
-                                    ),
-                                    self.builder.void_0(SPAN),
-                                    false,
-                                    false,
-                                    false,
-                                ),
-                            );
-                        }
-                        _ => {}
-                    }
-                    let names = self.extract_declared_names(&decl);
-                    let converted = self.convert_declaration(decl);
-                    match converted {
-                        Some(Expression::SequenceExpression(mut seq))
-                            if seq.expressions.len() > 1 =>
-                        {
-                            let diff = names.iter().map(|name| {
-                                let mut args = self.builder.vec();
-                                args.push(Argument::from(self.builder.expression_string_literal(
-                                    SPAN,
-                                    name.name.clone(),
-                                    None,
-                                )));
-                                args.push(Argument::from(
-                                    self.builder.expression_identifier(SPAN, name.name.clone()),
-                                ));
-                                self.builder.expression_call(
-                                    SPAN,
-                                    self.builder.expression_identifier(SPAN, "_exports"),
-                                    NONE,
-                                    args,
-                                    false,
-                                )
-                            });
-                            seq.expressions.extend(self.builder.vec_from_iter(diff));
-                            *it = self.builder.statement_expression(
-                                SPAN,
-                                self.builder.expression_sequence(
-                                    SPAN,
-                                    seq.expressions.take_in(self.allocator),
-                                ),
-                            )
-                        }
-                        Some(Expression::SequenceExpression(mut seq))
-                            if seq.expressions.len() == 1 =>
-                        {
-                            let expr = seq.expressions.pop().expect("Length must be 1.");
-                            if let Expression::AssignmentExpression(mut assignment) = expr {
-                                if assignment.left.is_simple_assignment_target() {
-                                    let name = assignment
-                                        .left
-                                        .to_simple_assignment_target()
-                                        .get_identifier_name()
-                                        .expect("Should have a name.");
-                                    let mut args = self.builder.vec();
-                                    args.push(Argument::from(
-                                        self.builder.expression_string_literal(SPAN, name, None),
-                                    ));
-                                    args.push(Argument::AssignmentExpression(
-                                        assignment.take_in_box(self.allocator),
-                                    ));
-                                    *it = self.builder.statement_expression(
-                                        SPAN,
-                                        self.builder.expression_call(
-                                            SPAN,
-                                            self.builder.expression_identifier(SPAN, "_exports"),
-                                            NONE,
-                                            args,
-                                            false,
-                                        ),
-                                    )
-                                } else {
-                                    let symbols = self.extract_assignment_symbols(&assignment.left);
-                                    let mut args =
-                                        self.builder.vec_from_iter(symbols.iter().map(|name| {
-                                            let mut args_exports = self.builder.vec();
-                                            args_exports.push(Argument::from(
-                                                self.builder.expression_string_literal(
-                                                    SPAN,
-                                                    name.name.clone(),
-                                                    None,
-                                                ),
-                                            ));
-                                            args_exports.push(Argument::from(
-                                                self.builder
-                                                    .expression_identifier(SPAN, name.name.clone()),
-                                            ));
-                                            self.builder.expression_call(
-                                                SPAN,
-                                                self.builder
-                                                    .expression_identifier(SPAN, "_exports"),
-                                                NONE,
-                                                args_exports,
-                                                false,
-                                            )
-                                        }));
-                                    args.insert(0, Expression::AssignmentExpression(assignment));
-                                    *it = self.builder.statement_expression(
+                            // `export class` always has a name.
+                            if let Some(class_name) = cls.id.as_ref().map(|id| id.name) {
+                                self.export_tree.push(
+                                    self.builder.object_property_kind_object_property(
                                         SPAN,
-                                        self.builder.expression_sequence(
+                                        PropertyKind::Init,
+                                        self.builder.property_key_static_identifier(
                                             SPAN,
-                                            self.builder.vec_from_iter(args),
+                                            class_name.clone_in(self.allocator),
                                         ),
-                                    );
-                                }
+                                        self.builder.void_0(SPAN),
+                                        false,
+                                        false,
+                                        false,
+                                    ),
+                                );
                             }
                         }
-                        None => {
-                            *it = self.builder.statement_empty(SPAN);
-                        }
-                        _ => {
-                            unimplemented!()
-                        }
+                        _ => {}
                     }
+                    *it = self.convert_declaration(decl);
                 } else {
-                    for spec in export.specifiers.iter_mut() {
-                        let local = spec.local.to_string();
-                        let exported = spec.exported.to_string();
-                        if is_identifier_name(exported.as_str()) {
-                            self.export_tree.push(
-                                self.builder.object_property_kind_object_property(
-                                    SPAN,
-                                    PropertyKind::Init,
-                                    if is_identifier_name(exported.as_str()) {
-                                        self.builder.property_key_static_identifier(
-                                            SPAN,
-                                            Atom::from_in(exported.as_str(), self.allocator),
-                                        )
-                                    } else {
-                                        ast::PropertyKey::from(
-                                            self.builder.expression_string_literal(
-                                                SPAN,
-                                                Atom::from_in(exported.as_str(), self.allocator),
-                                                None,
-                                            ),
-                                        )
-                                    },
-                                    self.builder.expression_identifier(
-                                        SPAN,
-                                        Atom::from_in(local.as_str(), self.allocator),
-                                    ),
-                                    false,
-                                    false,
-                                    false,
-                                ),
-                            )
-                        }
+                    for spec in export.specifiers.iter() {
+                        let property = self.builder.object_property_kind_object_property(
+                            SPAN,
+                            PropertyKind::Init,
+                            self.property_key(spec.exported.name()),
+                            self.builder.expression_identifier(SPAN, spec.local.name()),
+                            false,
+                            false,
+                            false,
+                        );
+                        self.export_tree.push(property);
                     }
                     *it = self.builder.statement_empty(SPAN);
                 }
             }
             Statement::ExportDefaultDeclaration(export_default) => {
-                if export_default.declaration.is_expression() {
-                    let expr = export_default
-                        .take_in(self.allocator)
-                        .declaration
-                        .into_expression();
-                    let mut args = self.builder.vec();
-                    args.push(Argument::from(
-                        self.builder
-                            .expression_string_literal(SPAN, "default", None),
-                    ));
-                    args.push(Argument::from(expr));
-                    *it = self.builder.statement_expression(
-                        SPAN,
-                        self.builder.expression_call(
-                            SPAN,
-                            self.builder.expression_identifier(SPAN, "_exports"),
-                            NONE,
-                            args,
-                            false,
-                        ),
-                    );
-                } else {
-                    match export_default.declaration.take_in(self.allocator) {
-                        ExportDefaultDeclarationKind::FunctionDeclaration(mut func) => {
-                            let fn_name = func
-                                .id
-                                .clone_in(self.allocator)
-                                .expect("Function declarations should have names.")
-                                .name;
-                            self.export_tree.push(
-                                self.builder.object_property_kind_object_property(
-                                    SPAN,
-                                    PropertyKind::Init,
-                                    self.builder.property_key_static_identifier(SPAN, "default"),
-                                    self.builder.expression_identifier(SPAN, fn_name),
-                                    false,
-                                    false,
-                                    false,
-                                ),
-                            );
-                            self.top_level_function_decls
-                                .push(func.take_in(self.allocator));
-                            *it = self.builder.statement_empty(SPAN);
-                        }
-                        ExportDefaultDeclarationKind::ClassDeclaration(mut class) => {
-                            self.top_level_classes.push(
-                                class
-                                    .id
-                                    .clone_in(self.allocator)
-                                    .expect("Class declarations should have names."),
-                            );
-                            self.export_tree.push(
-                                self.builder.object_property_kind_object_property(
-                                    SPAN,
-                                    PropertyKind::Init,
-                                    self.builder.property_key_static_identifier(SPAN, "default"),
-                                    self.builder.void_0(SPAN),
-                                    false,
-                                    false,
-                                    false,
-                                ),
-                            );
-                            *it = self.builder.statement_expression(
+                *it = match export_default.declaration.take_in(self.allocator) {
+                    ExportDefaultDeclarationKind::FunctionDeclaration(mut func) => {
+                        // export default function () {} -> function _default() {}
+                        let fn_name = match &func.id {
+                            Some(id) => id.name,
+                            None => {
+                                let name = self.used_names.unused_name("_default", self.allocator);
+                                func.id = Some(self.builder.binding_identifier(SPAN, name));
+                                name
+                            }
+                        };
+                        self.export_tree.push(
+                            self.builder.object_property_kind_object_property(
                                 SPAN,
-                                self.builder.expression_assignment(
-                                    SPAN,
-                                    AssignmentOperator::Assign,
-                                    self.builder
-                                        .simple_assignment_target_assignment_target_identifier(
-                                            SPAN,
-                                            class
-                                                .id
-                                                .as_ref()
-                                                .expect("Class declarations should have names.")
-                                                .name,
-                                        )
-                                        .into(),
-                                    self.builder.expression_class(
-                                        SPAN,
-                                        ClassType::ClassExpression,
-                                        class.decorators.take_in(self.allocator),
-                                        None,
-                                        NONE,
-                                        class.super_class.take(),
-                                        NONE,
-                                        class.implements.take_in(self.allocator),
-                                        class.body.take_in(self.allocator),
-                                        class.r#abstract,
-                                        class.declare,
-                                    ),
-                                ),
-                            );
-                        }
-                        _ => unreachable!(),
-                    };
-                }
+                                PropertyKind::Init,
+                                self.builder.property_key_static_identifier(SPAN, "default"),
+                                self.builder.expression_identifier(SPAN, fn_name),
+                                false,
+                                false,
+                                false,
+                            ),
+                        );
+                        self.top_level_function_decls
+                            .push(func.take_in(self.allocator));
+                        self.builder.statement_empty(SPAN)
+                    }
+                    ExportDefaultDeclarationKind::ClassDeclaration(mut class) => {
+                        // export default class {} -> _export("default", _default = class {})
+                        let (class_name, named) = match &class.id {
+                            Some(id) => (id.name, true),
+                            None => (self.used_names.unused_name("_default", self.allocator), false),
+                        };
+                        self.top_level_classes
+                            .push(self.builder.binding_identifier(SPAN, class_name));
+                        self.export_tree.push(
+                            self.builder.object_property_kind_object_property(
+                                SPAN,
+                                PropertyKind::Init,
+                                self.builder.property_key_static_identifier(SPAN, "default"),
+                                self.builder.void_0(SPAN),
+                                false,
+                                false,
+                                false,
+                            ),
+                        );
+                        let assignment = self.builder.expression_assignment(
+                            SPAN,
+                            AssignmentOperator::Assign,
+                            self.builder
+                                .simple_assignment_target_assignment_target_identifier(
+                                    SPAN, class_name,
+                                )
+                                .into(),
+                            self.class_declaration_to_expression(&mut class),
+                        );
+                        // Named classes are registered as exported bindings, so reassigning them
+                        // later keeps `default` up to date as well.
+                        let exported = if named {
+                            self.export_assignment(assignment, true)
+                        } else {
+                            self.export_call(Atom::from("default"), assignment)
+                        };
+                        self.builder.statement_expression(SPAN, exported)
+                    }
+                    // Type-only declarations have nothing to export at runtime.
+                    ExportDefaultDeclarationKind::TSInterfaceDeclaration(_) => {
+                        self.builder.statement_empty(SPAN)
+                    }
+                    // export default expr -> _export("default", expr)
+                    declaration => {
+                        let expr = declaration.into_expression();
+                        self.builder.statement_expression(
+                            SPAN,
+                            self.export_call(Atom::from("default"), expr),
+                        )
+                    }
+                };
             }
             _ => {}
         }
     }
 
+    fn visit_export_named_declaration(&mut self, it: &mut ExportNamedDeclaration<'a>) {
+        if let Some(source) = it.source.clone_in(self.allocator) {
+            self.add_dependency(
+                &source,
+                ImportMap::ExportNamedDeclaration(it.take_in_box(self.allocator)),
+            );
+        }
+        walk_mut::walk_export_named_declaration(self, it);
+    }
+
+    fn visit_property_definition(&mut self, it: &mut ast::PropertyDefinition<'a>) {
+        VisitMut::visit_decorators(self, &mut it.decorators);
+        VisitMut::visit_property_key(self, &mut it.key);
+        if let Some(value) = &mut it.value {
+            self.class_field_depth += 1;
+            VisitMut::visit_expression(self, value);
+            self.class_field_depth -= 1;
+        }
+    }
+
+    fn visit_accessor_property(&mut self, it: &mut ast::AccessorProperty<'a>) {
+        VisitMut::visit_decorators(self, &mut it.decorators);
+        VisitMut::visit_property_key(self, &mut it.key);
+        if let Some(value) = &mut it.value {
+            self.class_field_depth += 1;
+            VisitMut::visit_expression(self, value);
+            self.class_field_depth -= 1;
+        }
+    }
+
+    fn visit_function_body(&mut self, it: &mut ast::FunctionBody<'a>) {
+        self.function_temporaries.push(vec![]);
+        walk_mut::walk_function_body(self, it);
+        let temporaries = self.function_temporaries.pop().unwrap_or_default();
+        if !temporaries.is_empty() {
+            it.statements.insert(0, self.var_statement(&temporaries));
+        }
+    }
+
+    fn visit_arrow_function_expression(&mut self, it: &mut ast::ArrowFunctionExpression<'a>) {
+        if !it.expression {
+            walk_mut::walk_arrow_function_expression(self, it);
+            return;
+        }
+        // () => expr is visited as () => { return expr; }, whose value is kept, and only stays a
+        // block if temporaries were declared in it: () => { var _x; return expr; }
+        if let Some(Statement::ExpressionStatement(stmt)) = it.body.statements.pop() {
+            let argument = stmt.unbox().expression;
+            it.body.statements.push(self.builder.statement_return(SPAN, Some(argument)));
+        }
+        walk_mut::walk_arrow_function_expression(self, it);
+        if it.body.statements.len() > 1 {
+            it.expression = false;
+        } else if let Some(Statement::ReturnStatement(stmt)) = it.body.statements.pop() {
+            let expression = stmt.unbox().argument.unwrap_or_else(|| self.builder.void_0(SPAN));
+            it.body.statements.push(self.builder.statement_expression(SPAN, expression));
+        }
+    }
+
+    fn visit_static_block(&mut self, it: &mut ast::StaticBlock<'a>) {
+        self.function_temporaries.push(vec![]);
+        walk_mut::walk_static_block(self, it);
+        let temporaries = self.function_temporaries.pop().unwrap_or_default();
+        if !temporaries.is_empty() {
+            it.body.insert(0, self.var_statement(&temporaries));
+        }
+    }
+
+    fn visit_expression_statement(&mut self, it: &mut ast::ExpressionStatement<'a>) {
+        self.value_discarded = true;
+        walk_mut::walk_expression_statement(self, it);
+    }
+
+    fn visit_for_statement(&mut self, it: &mut ast::ForStatement<'a>) {
+        walk_mut::walk_for_statement(self, it);
+        // for (var i = 0; ...) -> for (i = 0; ...)
+        if let Some(ast::ForStatementInit::VariableDeclaration(decl)) = &mut it.init
+            && self.is_module_declaration(decl)
+        {
+            it.init = self.hoist_variable_declaration(decl).map(ast::ForStatementInit::from);
+        }
+    }
+
+    fn visit_for_in_statement(&mut self, it: &mut ast::ForInStatement<'a>) {
+        walk_mut::walk_for_in_statement(self, it);
+        self.convert_for_statement_left(&mut it.left);
+        self.export_for_statement_left(&it.left, &mut it.body);
+    }
+
+    fn visit_for_of_statement(&mut self, it: &mut ast::ForOfStatement<'a>) {
+        if it.r#await && self.is_module_scope_code() {
+            self.has_top_level_await = true;
+        }
+        walk_mut::walk_for_of_statement(self, it);
+        self.convert_for_statement_left(&mut it.left);
+        self.export_for_statement_left(&it.left, &mut it.body);
+    }
+
     fn visit_import_declaration(&mut self, it: &mut ImportDeclaration<'a>) {
-        self.importee.push(it.source.clone_in(self.allocator));
+        if it.phase.is_some() {
+            self.errors.push(TranspileError::unsupported(
+                "Import phases (`import source`, `import defer`) are not supported by SystemJS",
+                it.span,
+            ));
+        }
         if let Some(specifiers) = it.specifiers.as_ref() {
             for specifier in specifiers {
                 match specifier {
@@ -548,240 +530,892 @@ impl<'a> VisitMut<'a> for SystemJsTranspiler<'a> {
                 }
             }
         }
-        self.imports
-            .push(ImportMap::ImportDeclaration(it.take_in_box(self.allocator)));
+        let source = it.source.clone_in(self.allocator);
+        self.add_dependency(
+            &source,
+            ImportMap::ImportDeclaration(it.take_in_box(self.allocator)),
+        );
         walk_mut::walk_import_declaration(self, it);
     }
 
     fn visit_export_all_declaration(&mut self, it: &mut ExportAllDeclaration<'a>) {
-        self.importee.push(it.source.clone_in(self.allocator));
-        self.imports.push(ImportMap::ExportAllDeclaration(
-            it.take_in_box(self.allocator),
-        ));
+        let source = it.source.clone_in(self.allocator);
+        self.add_dependency(
+            &source,
+            ImportMap::ExportAllDeclaration(it.take_in_box(self.allocator)),
+        );
         walk_mut::walk_export_all_declaration(self, it);
     }
 }
 
 impl<'a> SystemJsTranspiler<'a> {
-    pub fn is_valid_tla_scope(&self) -> bool {
-        self.scope_stack
-            .iter()
-            .rev()
-            .all(|flag| flag.is_block() || flag.is_top())
+    /// `System`, or the configured member path such as `window.__loaders.app`.
+    fn system_global(&self) -> Expression<'a> {
+        let system_global = &self.options.system_global;
+        system_global.properties().iter().fold(
+            self.builder
+                .expression_identifier(SPAN, self.builder.atom(system_global.object())),
+            |object, property| {
+                Expression::from(self.builder.member_expression_static(
+                    SPAN,
+                    object,
+                    self.builder.identifier_name(SPAN, self.builder.atom(property)),
+                    false,
+                ))
+            },
+        )
     }
 
-    pub fn is_strict_top_level(&self) -> bool {
-        let strict =
-            matches!(self.path_stack.first(), Some(AstType::Program)) && self.path_stack.len() == 1;
-        strict
+    /// The `_export` parameter of the declaration function, as an expression.
+    fn export_function(&self) -> Expression<'a> {
+        self.builder.expression_identifier(SPAN, self.export_name)
     }
 
-    pub fn extract_assignment_symbols(
-        &self,
-        decl: &AssignmentTarget<'a>,
-    ) -> Vec<IdentifierReference<'a>> {
-        match decl {
-            AssignmentTarget::ArrayAssignmentTarget(arr) => {
-                let mut idents = vec![];
-                for elem in arr.elements.iter() {
-                    if let Some(elem) = elem.clone_in(self.allocator) {
-                        match elem {
-                            AssignmentTargetMaybeDefault::AssignmentTargetWithDefault(_) => {}
-                            _ => {
-                                idents.extend(
-                                    self.extract_assignment_symbols(&elem.into_assignment_target()),
-                                );
-                            }
-                        }
-                    }
-                }
-                if let Some(element) = arr.rest.clone_in(self.allocator) {
-                    idents.extend(self.extract_assignment_symbols(&element.target));
-                }
-                idents
-            }
-            AssignmentTarget::ObjectAssignmentTarget(obj) => {
-                let mut idents = vec![];
-                for elem in obj.properties.iter() {
-                    match elem {
-                        ast::AssignmentTargetProperty::AssignmentTargetPropertyIdentifier(
-                            ident,
-                        ) => {
-                            idents.push(ident.binding.clone_in(self.allocator));
-                        }
-                        ast::AssignmentTargetProperty::AssignmentTargetPropertyProperty(
-                            pattern,
-                        ) => match pattern.binding {
-                            AssignmentTargetMaybeDefault::AssignmentTargetWithDefault(_) => {}
-                            _ => {
-                                idents.extend(
-                                    self.extract_assignment_symbols(
-                                        &pattern
-                                            .binding
-                                            .clone_in(self.allocator)
-                                            .into_assignment_target(),
-                                    ),
-                                );
-                            }
-                        },
-                    }
-                }
-                idents
-            }
-            _ => {
-                if let Some(ident) = decl.to_simple_assignment_target().get_identifier_name() {
-                    vec![self.builder.identifier_reference(SPAN, ident)]
-                } else {
-                    vec![]
-                }
-            }
-        }
+    /// `_context.<property>`, e.g. `_context.import` or `_context.meta`.
+    fn context_member(&self, property: &'static str) -> Expression<'a> {
+        Expression::from(self.builder.member_expression_static(
+            SPAN,
+            self.builder.expression_identifier(SPAN, self.context_name),
+            self.builder.identifier_name(SPAN, property),
+            false,
+        ))
     }
 
-    pub fn extract_variable_symbols(
-        &self,
-        decl: ast::BindingPattern<'a>,
-    ) -> Vec<BindingIdentifier<'a>> {
-        match decl.kind {
-            ast::BindingPatternKind::BindingIdentifier(ident) => {
-                vec![ident.clone()]
-            }
-            ast::BindingPatternKind::ArrayPattern(mut pattern) => {
-                let mut idents = vec![];
-                for elem in pattern.elements.iter_mut() {
-                    if let Some(elem) = elem.clone_in(self.allocator) {
-                        let incremental = self.extract_variable_symbols(elem);
-                        idents.extend(incremental);
-                    }
-                }
-                idents
-            }
-            ast::BindingPatternKind::ObjectPattern(mut pattern) => {
-                let mut idents = vec![];
-                for prop in pattern.properties.iter_mut() {
-                    idents
-                        .extend(self.extract_variable_symbols(prop.value.clone_in(self.allocator)));
-                }
-                idents
-            }
-            ast::BindingPatternKind::AssignmentPattern(pattern) => {
-                self.extract_variable_symbols(pattern.left.clone_in(self.allocator))
-            }
+    /// `import(source, options)` becomes `_context.import(source, options)`, which loads the
+    /// module through SystemJS and resolves `source` relative to the current module.
+    fn convert_dynamic_import(&self, import: &mut ast::ImportExpression<'a>) -> Expression<'a> {
+        let mut args = self.builder.vec1(Argument::from(import.source.take_in(self.allocator)));
+        if let Some(options) = import.options.take() {
+            args.push(Argument::from(options));
         }
+        self.builder
+            .expression_call(import.span, self.context_member("import"), NONE, args, false)
     }
 
-    fn hacked_var_decl_to_assignment(
-        &self,
-        decl: &mut ast::VariableDeclaration<'a>,
-    ) -> Expression<'a> {
-        decl.kind = VariableDeclarationKind::Var;
-        let mut values = self.builder.vec();
-        for decl_item in decl.declarations.iter_mut() {
-            decl_item.kind = VariableDeclarationKind::Var;
-            if let Some(init_val) = decl_item.init.take() {
-                values.push(init_val);
-                decl_item.init = Some(self.builder.void_0(SPAN))
-            }
-        }
-        let simple_program = self.builder.program(
+    fn formal_parameter(&self, name: Atom<'a>) -> ast::FormalParameter<'a> {
+        self.builder.formal_parameter(
             SPAN,
-            SourceType::cjs(),
-            "",
             self.builder.vec(),
+            self.builder.binding_pattern(
+                self.builder.binding_pattern_kind_binding_identifier(SPAN, name),
+                NONE,
+                false,
+            ),
             None,
-            self.builder.vec(),
-            self.builder.vec1(Statement::VariableDeclaration(
-                decl.take_in_box(self.allocator),
-            )),
-        );
-        let generated_code = Codegen::new()
-            .build(&simple_program)
-            .code
-            .strip_prefix("var ")
-            .expect("Should include `var`.")
-            .to_string();
-        let parsed = Parser::new(self.allocator, generated_code.as_str(), SourceType::cjs())
-            .parse_expression()
-            .expect("Should be parsed.")
-            .clone_in(self.allocator);
-        let mut seq = match parsed {
-            Expression::AssignmentExpression(assignment) => self.builder.sequence_expression(
+            false,
+            false,
+        )
+    }
+
+    /// `var a, b;`
+    fn var_statement(&self, bindings: &[BindingIdentifier<'a>]) -> Statement<'a> {
+        let declarations = self.builder.vec_from_iter(bindings.iter().map(|binding| {
+            self.builder.variable_declarator(
                 SPAN,
-                self.builder
-                    .vec1(Expression::AssignmentExpression(assignment)),
-            ),
-            Expression::SequenceExpression(mut seq) => {
-                let mut exprs = seq.expressions.take_in(self.allocator);
-                exprs.retain(|e| matches!(e, Expression::AssignmentExpression(_)));
-                self.builder.sequence_expression(SPAN, exprs)
-            }
-            _ => self.builder.sequence_expression(SPAN, self.builder.vec()),
-        };
-        for (item, value) in seq.expressions.iter_mut().zip(values) {
-            if let Expression::AssignmentExpression(assignment) = item {
-                assignment.right = value
-            }
-        }
-        Expression::SequenceExpression(self.builder.alloc(seq))
+                VariableDeclarationKind::Var,
+                self.builder.binding_pattern(
+                    self.builder
+                        .binding_pattern_kind_binding_identifier(SPAN, binding.name),
+                    NONE,
+                    false,
+                ),
+                None,
+                false,
+            )
+        }));
+        Statement::VariableDeclaration(self.builder.alloc_variable_declaration(
+            SPAN,
+            VariableDeclarationKind::Var,
+            declarations,
+            false,
+        ))
     }
 
-    fn convert_declaration(&mut self, it: Declaration<'a>) -> Option<Expression<'a>> {
-        match it {
-            Declaration::VariableDeclaration(mut it)
-                if (self.is_valid_tla_scope()
-                    && matches!(it.kind, VariableDeclarationKind::Var))
-                    || self.is_strict_top_level() =>
-            {
-                for pattern in it.declarations.iter() {
-                    self.top_level_vars
-                        .extend(self.extract_variable_symbols(pattern.id.clone_in(self.allocator)));
-                }
-                Some(self.hacked_var_decl_to_assignment(&mut it))
-            }
-            Declaration::FunctionDeclaration(mut function) if self.is_valid_tla_scope() => {
-                self.top_level_function_decls
-                    .push(function.take_in(self.allocator));
-                None
-            }
-            Declaration::ClassDeclaration(mut decl) if self.is_valid_tla_scope() => {
-                if let Some(id) = decl.id.as_ref() {
-                    self.top_level_classes.push(id.clone_in(self.allocator));
-                }
-                Some(
-                    self.builder.expression_sequence(
+    /// Whether the code being visited runs as part of the module body rather than a function.
+    fn is_module_scope_code(&self) -> bool {
+        !self.scope_stack.iter().any(|flags| flags.is_function())
+    }
+
+    /// The `execute` function of the registration. Modules with top-level await get an async
+    /// `execute`; when the transform target cannot express async functions, it is down-levelled
+    /// to `function () { return _asyncToGenerator(function* () { ... }).call(this); }` instead,
+    /// with every top-level `await` already turned into `yield`.
+    fn build_execute_function(
+        &self,
+        body: ArenaVec<'a, Statement<'a>>,
+        async_to_generator: Option<Atom<'a>>,
+    ) -> Expression<'a> {
+        let empty_params = || {
+            self.builder.formal_parameters(
+                SPAN,
+                FormalParameterKind::FormalParameter,
+                self.builder.vec(),
+                NONE,
+            )
+        };
+        let Some(async_to_generator) = async_to_generator else {
+            return self.builder.expression_function(
+                SPAN,
+                FunctionType::FunctionExpression,
+                None,
+                false,
+                self.has_top_level_await,
+                false,
+                NONE,
+                NONE,
+                empty_params(),
+                NONE,
+                Some(self.builder.function_body(SPAN, self.builder.vec(), body)),
+            );
+        };
+        let generator = self.builder.expression_function(
+            SPAN,
+            FunctionType::FunctionExpression,
+            None,
+            true,
+            false,
+            false,
+            NONE,
+            NONE,
+            empty_params(),
+            NONE,
+            Some(self.builder.function_body(SPAN, self.builder.vec(), body)),
+        );
+        let wrapped = self.builder.expression_call(
+            SPAN,
+            self.builder.expression_identifier(SPAN, async_to_generator),
+            NONE,
+            self.builder.vec1(Argument::from(generator)),
+            false,
+        );
+        let invocation = self.builder.expression_call(
+            SPAN,
+            Expression::from(self.builder.member_expression_static(
+                SPAN,
+                wrapped,
+                self.builder.identifier_name(SPAN, "call"),
+                false,
+            )),
+            NONE,
+            self.builder.vec1(Argument::from(self.builder.expression_this(SPAN))),
+            false,
+        );
+        self.builder.expression_function(
+            SPAN,
+            FunctionType::FunctionExpression,
+            None,
+            false,
+            false,
+            false,
+            NONE,
+            NONE,
+            empty_params(),
+            NONE,
+            Some(self.builder.function_body(
+                SPAN,
+                self.builder.vec(),
+                self.builder.vec1(self.builder.statement_return(SPAN, Some(invocation))),
+            )),
+        )
+    }
+
+    /// The local name of the `asyncToGenerator` runtime helper. The transformer already imports
+    /// it when the module contains async functions; otherwise it is added as a dependency.
+    fn async_to_generator_helper(&mut self) -> Atom<'a> {
+        let existing = self
+            .dependencies
+            .iter()
+            .flat_map(|dependency| dependency.entries.iter())
+            .find_map(|import| match import {
+            ImportMap::ImportDeclaration(decl) if decl.source.value == ASYNC_TO_GENERATOR => {
+                decl.specifiers.as_ref()?.iter().find_map(|specifier| match specifier {
+                    ImportDeclarationSpecifier::ImportDefaultSpecifier(default) => {
+                        Some(default.local.name)
+                    }
+                    _ => None,
+                })
+            }
+            _ => None,
+        });
+        if let Some(local) = existing {
+            return local;
+        }
+        let local = self.used_names.unused_name("_asyncToGenerator", self.allocator);
+        let source = self.builder.string_literal(SPAN, ASYNC_TO_GENERATOR, None);
+        let binding = self.builder.binding_identifier(SPAN, local);
+        self.imported_symbols.push(binding.clone_in(self.allocator));
+        let import = self.builder.alloc_import_declaration(
+            SPAN,
+            Some(self.builder.vec1(
+                self.builder
+                    .import_declaration_specifier_import_default_specifier(SPAN, binding),
+            )),
+            source.clone_in(self.allocator),
+            None,
+            NONE,
+            ImportOrExportKind::Value,
+        );
+        self.add_dependency(&source, ImportMap::ImportDeclaration(import));
+        local
+    }
+
+    /// Whether `this` at the current position is the `this` of the module itself, which is
+    /// `undefined`. Arrow functions inherit it, while other functions, class static blocks and
+    /// class field initializers bind their own.
+    fn is_module_this(&self) -> bool {
+        self.class_field_depth == 0
+            && self.scope_stack.iter().all(|flags| {
+                flags.is_arrow() || !(flags.is_function() || flags.is_class_static_block())
+            })
+    }
+
+    /// Whether `ident` declares a binding of the module scope, i.e. one that is hoisted into the
+    /// outer declaration function. `var`s nested in module-level blocks belong to the module
+    /// scope as well, while block-scoped declarations only do at the top level.
+    fn is_module_binding(&self, ident: &BindingIdentifier<'a>) -> bool {
+        match ident.symbol_id.get() {
+            Some(symbol_id) => {
+                self.scoping.symbol_scope_id(symbol_id) == self.scoping.root_scope_id()
+            }
+            None => self.scope_stack.len() == 1,
+        }
+    }
+
+    /// Whether `ident` refers to a binding of the module scope rather than a local that shadows
+    /// it. References without semantic information were created by the transpiler itself for
+    /// hoisted bindings.
+    fn is_module_reference(&self, ident: &IdentifierReference<'a>) -> bool {
+        let Some(reference_id) = ident.reference_id.get() else {
+            return true;
+        };
+        self.scoping
+            .get_reference(reference_id)
+            .symbol_id()
+            .is_some_and(|symbol_id| {
+                self.scoping.symbol_scope_id(symbol_id) == self.scoping.root_scope_id()
+            })
+    }
+
+    /// Every identifier an assignment target writes to, including the ones behind defaults and
+    /// rest elements: `[a, b = 1, ...c]` and `{ x: d = 2, [key]: e, ...f }`.
+    pub fn extract_assignment_symbols(
+        &self,
+        target: &AssignmentTarget<'a>,
+    ) -> Vec<IdentifierReference<'a>> {
+        let mut idents = vec![];
+        match target {
+            AssignmentTarget::ArrayAssignmentTarget(arr) => {
+                for elem in arr.elements.iter().flatten() {
+                    idents.extend(self.extract_maybe_default_symbols(elem));
+                }
+                if let Some(rest) = &arr.rest {
+                    idents.extend(self.extract_assignment_symbols(&rest.target));
+                }
+            }
+            AssignmentTarget::ObjectAssignmentTarget(obj) => {
+                for prop in obj.properties.iter() {
+                    match prop {
+                        ast::AssignmentTargetProperty::AssignmentTargetPropertyIdentifier(
+                            ident,
+                        ) => {
+                            idents.push(ident.binding.clone_in_with_semantic_ids(self.allocator));
+                        }
+                        ast::AssignmentTargetProperty::AssignmentTargetPropertyProperty(
+                            prop,
+                        ) => {
+                            idents.extend(self.extract_maybe_default_symbols(&prop.binding));
+                        }
+                    }
+                }
+                if let Some(rest) = &obj.rest {
+                    idents.extend(self.extract_assignment_symbols(&rest.target));
+                }
+            }
+            AssignmentTarget::AssignmentTargetIdentifier(ident) => {
+                idents.push(ident.as_ref().clone_in_with_semantic_ids(self.allocator));
+            }
+            _ => {}
+        }
+        idents
+    }
+
+    fn extract_maybe_default_symbols(
+        &self,
+        target: &AssignmentTargetMaybeDefault<'a>,
+    ) -> Vec<IdentifierReference<'a>> {
+        match target {
+            AssignmentTargetMaybeDefault::AssignmentTargetWithDefault(with_default) => {
+                self.extract_assignment_symbols(&with_default.binding)
+            }
+            _ => target
+                .as_assignment_target()
+                .map(|target| self.extract_assignment_symbols(target))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Every binding a pattern declares, including the ones behind defaults and rest elements.
+    pub fn extract_variable_symbols(
+        &self,
+        pattern: &BindingPattern<'a>,
+    ) -> Vec<BindingIdentifier<'a>> {
+        let mut idents = vec![];
+        match &pattern.kind {
+            BindingPatternKind::BindingIdentifier(ident) => {
+                idents.push(ident.as_ref().clone_in_with_semantic_ids(self.allocator));
+            }
+            BindingPatternKind::ArrayPattern(array) => {
+                for element in array.elements.iter().flatten() {
+                    idents.extend(self.extract_variable_symbols(element));
+                }
+                if let Some(rest) = &array.rest {
+                    idents.extend(self.extract_variable_symbols(&rest.argument));
+                }
+            }
+            BindingPatternKind::ObjectPattern(object) => {
+                for property in object.properties.iter() {
+                    idents.extend(self.extract_variable_symbols(&property.value));
+                }
+                if let Some(rest) = &object.rest {
+                    idents.extend(self.extract_variable_symbols(&rest.argument));
+                }
+            }
+            BindingPatternKind::AssignmentPattern(assignment) => {
+                idents.extend(self.extract_variable_symbols(&assignment.left));
+            }
+        }
+        idents
+    }
+
+    /// Converts the declarators of a hoisted declaration into assignments to the hoisted
+    /// bindings: `let a = 1, { b, c: [d = 2, ...e] } = f;` becomes
+    /// `a = 1, { b, c: [d = 2, ...e] } = f`. Declarators without an initializer are dropped for
+    /// `var` (which must not reset the binding) and assign `void 0` otherwise.
+    fn var_declaration_to_assignments(
+        &self,
+        decl: &mut ast::VariableDeclaration<'a>,
+    ) -> ArenaVec<'a, Expression<'a>> {
+        let mut assignments = self.builder.vec();
+        for declarator in decl.declarations.take_in(self.allocator) {
+            let init = match declarator.init {
+                Some(init) => init,
+                None if decl.kind == VariableDeclarationKind::Var => continue,
+                None => self.builder.void_0(SPAN),
+            };
+            assignments.push(self.builder.expression_assignment(
+                declarator.span,
+                AssignmentOperator::Assign,
+                self.binding_pattern_to_assignment_target(declarator.id),
+                init,
+            ));
+        }
+        assignments
+    }
+
+    fn binding_pattern_to_assignment_target(
+        &self,
+        pattern: BindingPattern<'a>,
+    ) -> AssignmentTarget<'a> {
+        match pattern.kind {
+            BindingPatternKind::BindingIdentifier(ident) => AssignmentTarget::from(
+                self.builder
+                    .simple_assignment_target_assignment_target_identifier(ident.span, ident.name),
+            ),
+            BindingPatternKind::ArrayPattern(array) => {
+                let array = array.unbox();
+                let elements = self.builder.vec_from_iter(array.elements.into_iter().map(
+                    |element| element.map(|element| self.binding_pattern_to_maybe_default(element)),
+                ));
+                let rest = array.rest.map(|rest| self.binding_rest_to_assignment_target_rest(rest));
+                AssignmentTarget::ArrayAssignmentTarget(
+                    self.builder
+                        .alloc_array_assignment_target(array.span, elements, rest),
+                )
+            }
+            BindingPatternKind::ObjectPattern(object) => {
+                let object = object.unbox();
+                let properties = self.builder.vec_from_iter(
+                    object
+                        .properties
+                        .into_iter()
+                        .map(|property| self.binding_property_to_assignment_target_property(property)),
+                );
+                let rest = object.rest.map(|rest| self.binding_rest_to_assignment_target_rest(rest));
+                AssignmentTarget::ObjectAssignmentTarget(
+                    self.builder
+                        .alloc_object_assignment_target(object.span, properties, rest),
+                )
+            }
+            // Defaults only exist inside patterns and are handled by the callers.
+            BindingPatternKind::AssignmentPattern(assignment) => {
+                self.binding_pattern_to_assignment_target(assignment.unbox().left)
+            }
+        }
+    }
+
+    /// An element of an array pattern or the value of an object pattern property, which may
+    /// carry a default value: `a = 1` in `[a = 1]` or `{ x: a = 1 }`.
+    fn binding_pattern_to_maybe_default(
+        &self,
+        pattern: BindingPattern<'a>,
+    ) -> AssignmentTargetMaybeDefault<'a> {
+        match pattern.kind {
+            BindingPatternKind::AssignmentPattern(assignment) => {
+                let assignment = assignment.unbox();
+                self.builder
+                    .assignment_target_maybe_default_assignment_target_with_default(
+                        assignment.span,
+                        self.binding_pattern_to_assignment_target(assignment.left),
+                        assignment.right,
+                    )
+            }
+            _ => AssignmentTargetMaybeDefault::from(
+                self.binding_pattern_to_assignment_target(pattern),
+            ),
+        }
+    }
+
+    fn binding_property_to_assignment_target_property(
+        &self,
+        property: ast::BindingProperty<'a>,
+    ) -> ast::AssignmentTargetProperty<'a> {
+        let value = match property.value.kind {
+            // { a }
+            BindingPatternKind::BindingIdentifier(ident) if property.shorthand => {
+                return self
+                    .builder
+                    .assignment_target_property_assignment_target_property_identifier(
+                        property.span,
+                        self.builder.identifier_reference(ident.span, ident.name),
+                        None,
+                    );
+            }
+            // { a = 1 }
+            BindingPatternKind::AssignmentPattern(assignment) if property.shorthand => {
+                let assignment = assignment.unbox();
+                if let Some(ident) = assignment.left.get_binding_identifier() {
+                    let binding = self.builder.identifier_reference(ident.span, ident.name);
+                    return self
+                        .builder
+                        .assignment_target_property_assignment_target_property_identifier(
+                            property.span,
+                            binding,
+                            Some(assignment.right),
+                        );
+                }
+                self.builder.binding_pattern(
+                    BindingPatternKind::AssignmentPattern(self.builder.alloc(assignment)),
+                    NONE,
+                    false,
+                )
+            }
+            kind => self.builder.binding_pattern(kind, NONE, false),
+        };
+        // { key: value }, { [key]: value = 1 }
+        self.builder
+            .assignment_target_property_assignment_target_property_property(
+                property.span,
+                property.key,
+                self.binding_pattern_to_maybe_default(value),
+                property.computed,
+            )
+    }
+
+    fn binding_rest_to_assignment_target_rest(
+        &self,
+        rest: ArenaBox<'a, ast::BindingRestElement<'a>>,
+    ) -> ArenaBox<'a, ast::AssignmentTargetRest<'a>> {
+        let rest = rest.unbox();
+        self.builder.alloc_assignment_target_rest(
+            rest.span,
+            self.binding_pattern_to_assignment_target(rest.argument),
+        )
+    }
+
+    /// Whether every binding of `decl` belongs to the module scope, so that the whole declaration
+    /// can be hoisted.
+    fn is_module_declaration(&self, decl: &ast::VariableDeclaration<'a>) -> bool {
+        decl.declarations.iter().all(|declarator| {
+            self.extract_variable_symbols(&declarator.id)
+                .iter()
+                .all(|ident| self.is_module_binding(ident))
+        })
+    }
+
+    /// Hoists the bindings of a module-level variable declaration and returns the (exported)
+    /// assignments that replace it, if it initializes anything.
+    fn hoist_variable_declaration(
+        &mut self,
+        decl: &mut ast::VariableDeclaration<'a>,
+    ) -> Option<Expression<'a>> {
+        for pattern in decl.declarations.iter() {
+            self.top_level_vars
+                .extend(self.extract_variable_symbols(&pattern.id));
+        }
+        let assignments = self.var_declaration_to_assignments(decl);
+        self.export_assignments(assignments)
+    }
+
+    /// `for (var k in obj)` and `for (var x of xs)` at module scope: hoists `k` and assigns it
+    /// directly, `for (k in obj)`.
+    fn convert_for_statement_left(&mut self, left: &mut ast::ForStatementLeft<'a>) {
+        let ast::ForStatementLeft::VariableDeclaration(decl) = left else {
+            return;
+        };
+        if !self.is_module_declaration(decl) {
+            return;
+        }
+        let Some(declarator) = decl.declarations.pop() else {
+            return;
+        };
+        self.top_level_vars
+            .extend(self.extract_variable_symbols(&declarator.id));
+        *left = ast::ForStatementLeft::from(self.binding_pattern_to_assignment_target(declarator.id));
+    }
+
+    /// `for (k of xs) body` assigns `k` on every iteration, so exported bindings among its
+    /// targets are exported again at the start of the body:
+    /// `for (k of xs) { _export("k", k); body }`.
+    fn export_for_statement_left(
+        &self,
+        left: &ast::ForStatementLeft<'a>,
+        body: &mut Statement<'a>,
+    ) {
+        let Some(target) = left.as_assignment_target() else {
+            return;
+        };
+        let exports = self.export_assignment_targets(target);
+        if exports.is_empty() {
+            return;
+        }
+        let mut stmts = self.builder.vec_from_iter(
+            exports
+                .into_iter()
+                .map(|export| self.builder.statement_expression(SPAN, export)),
+        );
+        match body {
+            Statement::BlockStatement(block) => {
+                stmts.extend(block.body.take_in(self.allocator));
+                block.body = stmts;
+            }
+            body => {
+                stmts.push(body.take_in(self.allocator));
+                *body = self.builder.statement_block(SPAN, stmts);
+            }
+        }
+    }
+
+    /// Converts a module-level declaration into the statement that replaces it in `execute`.
+    /// Hoisted declarations leave their bindings in the outer declaration function: variables
+    /// and classes become assignments, and top-level functions are moved out entirely.
+    fn convert_declaration(&mut self, it: Declaration<'a>) -> Statement<'a> {
+        match it {
+            Declaration::VariableDeclaration(mut it) if self.is_module_declaration(&it) => {
+                match self.hoist_variable_declaration(&mut it) {
+                    Some(assignments) => self.builder.statement_expression(SPAN, assignments),
+                    None => self.builder.statement_empty(SPAN),
+                }
+            }
+            Declaration::FunctionDeclaration(mut function)
+                if function.id.as_ref().is_some_and(|id| self.is_module_binding(id)) =>
+            {
+                self.top_level_function_decls
+                    .push(function.take_in(self.allocator));
+                self.builder.statement_empty(SPAN)
+            }
+            Declaration::ClassDeclaration(mut decl)
+                if decl.id.as_ref().is_some_and(|id| self.is_module_binding(id)) =>
+            {
+                let Some(id) = decl.id.clone_in(self.allocator) else {
+                    return Statement::ClassDeclaration(decl);
+                };
+                let name = id.name;
+                self.top_level_classes.push(id);
+                let assignment = self.builder.expression_assignment(
+                    SPAN,
+                    AssignmentOperator::Assign,
+                    self.builder
+                        .simple_assignment_target_assignment_target_identifier(SPAN, name)
+                        .into(),
+                    self.class_declaration_to_expression(&mut decl),
+                );
+                self.builder
+                    .statement_expression(SPAN, self.export_assignment(assignment, true))
+            }
+            declaration => Statement::from(declaration),
+        }
+    }
+
+    /// Turns `class A {}` into the named class expression `class A {}`, so that `A.name`, stack
+    /// traces and the immutable inner binding of `A` are preserved once it is assigned to the
+    /// hoisted `A`.
+    fn class_declaration_to_expression(&self, class: &mut ast::Class<'a>) -> Expression<'a> {
+        let mut expression = class.take_in(self.allocator);
+        expression.r#type = ClassType::ClassExpression;
+        Expression::ClassExpression(self.builder.alloc(expression))
+    }
+
+    /// Routes every assignment of a converted declaration through [`Self::export_assignment`],
+    /// joining them into a single expression.
+    fn export_assignments(
+        &mut self,
+        assignments: ArenaVec<'a, Expression<'a>>,
+    ) -> Option<Expression<'a>> {
+        let mut expressions = self.builder.vec();
+        for assignment in assignments {
+            // The value of a declaration is never used.
+            expressions.push(self.export_assignment(assignment, false));
+        }
+        match expressions.len() {
+            0 | 1 => expressions.pop(),
+            _ => Some(self.builder.expression_sequence(SPAN, expressions)),
+        }
+    }
+
+    /// Builds `_export("exported", value)`.
+    fn export_call(&self, exported: Atom<'a>, value: Expression<'a>) -> Expression<'a> {
+        let mut args = self.builder.vec();
+        args.push(Argument::from(
+            self.builder.expression_string_literal(SPAN, exported, None),
+        ));
+        args.push(Argument::from(value));
+        self.builder
+            .expression_call(SPAN, self.export_function(), NONE, args, false)
+    }
+
+    /// Wraps `value` in one `_export` call per name the binding `local` is exported as.
+    fn export_binding(&self, local: &str, mut value: Expression<'a>) -> Expression<'a> {
+        if let Some(exported_names) = self.exported_bindings.get(local) {
+            for exported in exported_names {
+                value = self.export_call(*exported, value);
+            }
+        }
+        value
+    }
+
+    fn is_exported_binding(&self, local: &str) -> bool {
+        self.exported_bindings.contains_key(local)
+    }
+
+    /// Pushes the new value of an exported binding through `_export` whenever it is reassigned,
+    /// which is what keeps the live bindings of importers up to date. With `keep_value`, the
+    /// rewritten expression still evaluates to the value of the original one:
+    ///
+    /// - `x = v`, `x += v` becomes `_export("x", x = v)`
+    /// - `++x` becomes `_export("x", ++x)`, and `x++` becomes `(_x = x++, _export("x", x), _x)`
+    /// - `[x, y] = v` becomes `(_ref = v, [x, y] = _ref, _export("x", x), _export("y", y), _ref)`
+    fn export_assignment(&mut self, expr: Expression<'a>, keep_value: bool) -> Expression<'a> {
+        match expr {
+            Expression::AssignmentExpression(mut assignment) => {
+                if let AssignmentTarget::AssignmentTargetIdentifier(ident) = &assignment.left {
+                    if !self.is_module_reference(ident) {
+                        return Expression::AssignmentExpression(assignment);
+                    }
+                    let name = ident.name;
+                    return self.export_binding(&name, Expression::AssignmentExpression(assignment));
+                }
+                let exports = self.export_assignment_targets(&assignment.left);
+                if exports.is_empty() {
+                    return Expression::AssignmentExpression(assignment);
+                }
+                let mut expressions = self.builder.vec();
+                let value = keep_value.then(|| {
+                    // _ref = v, [x, y] = _ref
+                    let value = self.temporary("_ref");
+                    let right = assignment.right.take_in(self.allocator);
+                    expressions.push(self.assign_temporary(value, right));
+                    assignment.right = self.builder.expression_identifier(SPAN, value);
+                    value
+                });
+                expressions.push(Expression::AssignmentExpression(assignment));
+                expressions.extend(exports);
+                if let Some(value) = value {
+                    expressions.push(self.builder.expression_identifier(SPAN, value));
+                }
+                self.builder.expression_sequence(SPAN, expressions)
+            }
+            Expression::UpdateExpression(update) => {
+                let ast::SimpleAssignmentTarget::AssignmentTargetIdentifier(ident) =
+                    &update.argument
+                else {
+                    return Expression::UpdateExpression(update);
+                };
+                if !self.is_exported_binding(&ident.name) || !self.is_module_reference(ident) {
+                    return Expression::UpdateExpression(update);
+                }
+                let name = ident.name;
+                if update.prefix {
+                    return self.export_binding(&name, Expression::UpdateExpression(update));
+                }
+                let export =
+                    self.export_binding(&name, self.builder.expression_identifier(SPAN, name));
+                if !keep_value {
+                    // x++, _export("x", x)
+                    return self.builder.expression_sequence(
                         SPAN,
-                        self.builder.vec1(
-                            self.builder.expression_assignment(
-                                SPAN,
-                                AssignmentOperator::Assign,
-                                self.builder
-                                    .simple_assignment_target_assignment_target_identifier(
-                                        SPAN,
-                                        decl.id
-                                            .as_ref()
-                                            .expect("Should have a name for ClassDeclaration.")
-                                            .name,
-                                    )
-                                    .into(),
-                                self.builder.expression_class(
-                                    SPAN,
-                                    ClassType::ClassExpression,
-                                    decl.decorators.take_in(self.allocator),
-                                    None,
-                                    NONE,
-                                    decl.super_class.take(),
-                                    NONE,
-                                    decl.implements.take_in(self.allocator),
-                                    decl.body.take_in(self.allocator),
-                                    decl.r#abstract,
-                                    decl.declare,
-                                ),
-                            ),
-                        ),
-                    ),
+                        self.builder.vec_from_array([Expression::UpdateExpression(update), export]),
+                    );
+                }
+                // _x = x++, _export("x", x), _x
+                let old_value = self.temporary(&format!("_{name}"));
+                self.builder.expression_sequence(
+                    SPAN,
+                    self.builder.vec_from_array([
+                        self.assign_temporary(old_value, Expression::UpdateExpression(update)),
+                        export,
+                        self.builder.expression_identifier(SPAN, old_value),
+                    ]),
                 )
             }
-            _ => None,
+            expr => expr,
+        }
+    }
+
+    /// `_export("x", x)` for every exported module binding a destructuring target writes to.
+    fn export_assignment_targets(&self, target: &AssignmentTarget<'a>) -> Vec<Expression<'a>> {
+        self.extract_assignment_symbols(target)
+            .into_iter()
+            .filter(|ident| {
+                self.is_exported_binding(&ident.name) && self.is_module_reference(ident)
+            })
+            .map(|ident| {
+                let value = self.builder.expression_identifier(SPAN, ident.name);
+                self.export_binding(&ident.name, value)
+            })
+            .collect()
+    }
+
+    /// Allocates a fresh variable for an intermediate value, declared in the nearest function
+    /// so that re-entrant calls (e.g. through the setters `_export` runs) get their own copy.
+    fn temporary(&mut self, base: &str) -> Atom<'a> {
+        let name = self.used_names.unused_name(base, self.allocator);
+        let binding = self.builder.binding_identifier(SPAN, name);
+        match self.function_temporaries.last_mut() {
+            Some(temporaries) => temporaries.push(binding),
+            None => self.top_level_vars.push(binding),
+        }
+        name
+    }
+
+    fn assign_temporary(&self, name: Atom<'a>, value: Expression<'a>) -> Expression<'a> {
+        self.builder.expression_assignment(
+            SPAN,
+            AssignmentOperator::Assign,
+            AssignmentTarget::from(
+                self.builder.simple_assignment_target_assignment_target_identifier(SPAN, name),
+            ),
+            value,
+        )
+    }
+
+    /// Collects the exports of the module up front, so that assignments which appear before the
+    /// `export` statement can be rewritten as well, and so that `export *` setters know which
+    /// names the module exports itself.
+    fn collect_exports(&mut self, program: &Program<'a>) {
+        let mut star_exports = HashSet::new();
+        for stmt in program.body.iter() {
+            match stmt {
+                Statement::ExportNamedDeclaration(export) if export.source.is_none() => {
+                    if let Some(decl) = &export.declaration {
+                        // Temporaries the transformer added to the declaration (e.g. for
+                        // object rest) have no source span and are not exports.
+                        for ident in self
+                            .extract_declared_names(decl)
+                            .into_iter()
+                            .filter(|ident| !ident.span.is_empty())
+                        {
+                            self.add_exported_binding(ident.name, ident.name);
+                            self.explicit_exports.push(ident.name);
+                        }
+                    }
+                    for spec in export.specifiers.iter() {
+                        self.add_exported_binding(spec.local.name(), spec.exported.name());
+                        self.explicit_exports.push(spec.exported.name());
+                    }
+                }
+                Statement::ExportNamedDeclaration(export) => {
+                    self.explicit_exports
+                        .extend(export.specifiers.iter().map(|spec| spec.exported.name()));
+                }
+                Statement::ExportAllDeclaration(export) => match &export.exported {
+                    Some(exported) => self.explicit_exports.push(exported.name()),
+                    None => {
+                        star_exports.insert(export.source.value);
+                    }
+                },
+                Statement::ExportDefaultDeclaration(export_default) => {
+                    self.explicit_exports.push(Atom::from("default"));
+                    let id = match &export_default.declaration {
+                        ExportDefaultDeclarationKind::FunctionDeclaration(func) => func.id.as_ref(),
+                        ExportDefaultDeclarationKind::ClassDeclaration(class) => class.id.as_ref(),
+                        _ => None,
+                    };
+                    if let Some(id) = id {
+                        self.add_exported_binding(id.name, Atom::from("default"));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !star_exports.is_empty() && !self.explicit_exports.is_empty() {
+            self.export_names_object =
+                Some(self.used_names.unused_name("_exportNames", self.allocator));
+        }
+        if !star_exports.is_empty() {
+            self.export_object_name = self.used_names.unused_name("_exportObj", self.allocator);
+            self.export_key_name = self.used_names.unused_name("_key", self.allocator);
+        }
+        if star_exports.len() > 1 {
+            self.star_export_namespaces = (0..star_exports.len())
+                .map(|_| self.used_names.unused_name("_exportStar", self.allocator))
+                .collect();
+        }
+    }
+
+    /// `var _exportNames = { a: true, ... };`, the names `export *` must not override.
+    fn build_export_names_declaration(&self, export_names: Atom<'a>) -> Statement<'a> {
+        let properties = self.builder.vec_from_iter(self.explicit_exports.iter().map(|name| {
+            self.builder.object_property_kind_object_property(
+                SPAN,
+                PropertyKind::Init,
+                self.property_key(*name),
+                self.builder.expression_boolean_literal(SPAN, true),
+                false,
+                false,
+                false,
+            )
+        }));
+        Statement::from(self.builder.declaration_variable(
+            SPAN,
+            VariableDeclarationKind::Var,
+            self.builder.vec1(self.builder.variable_declarator(
+                SPAN,
+                VariableDeclarationKind::Var,
+                self.builder.binding_pattern(
+                    self.builder.binding_pattern_kind_binding_identifier(SPAN, export_names),
+                    NONE,
+                    false,
+                ),
+                Some(self.builder.expression_object(SPAN, properties)),
+                false,
+            )),
+            false,
+        ))
+    }
+
+    /// A static property key for identifier names, a string literal key otherwise.
+    fn property_key(&self, name: Atom<'a>) -> ast::PropertyKey<'a> {
+        if is_identifier_name(name.as_str()) {
+            self.builder.property_key_static_identifier(SPAN, name)
+        } else {
+            ast::PropertyKey::from(self.builder.expression_string_literal(SPAN, name, None))
+        }
+    }
+
+    fn add_exported_binding(&mut self, local: Atom<'a>, exported: Atom<'a>) {
+        let exported_names = self.exported_bindings.entry(local).or_default();
+        if !exported_names.contains(&exported) {
+            exported_names.push(exported);
         }
     }
 
@@ -790,38 +1424,26 @@ impl<'a> SystemJsTranspiler<'a> {
             Declaration::VariableDeclaration(it) => Vec::from_iter(
                 it.declarations
                     .iter()
-                    .map(|decl| self.extract_variable_symbols(decl.id.clone_in(self.allocator)))
-                    .flatten(),
+                    .flat_map(|decl| self.extract_variable_symbols(&decl.id)),
             ),
-            Declaration::FunctionDeclaration(it) => {
-                vec![
-                    it.id
-                        .clone()
-                        .expect("Function declarations should have names."),
-                ]
-            }
-            Declaration::ClassDeclaration(it) => {
-                vec![
-                    it.id
-                        .clone()
-                        .expect("Class declarations should have names."),
-                ]
-            }
-            _ => unreachable!(),
+            Declaration::FunctionDeclaration(it) => it.id.iter().cloned().collect(),
+            Declaration::ClassDeclaration(it) => it.id.iter().cloned().collect(),
+            // TypeScript declarations have no runtime bindings.
+            _ => vec![],
         }
     }
 
     /// Ported from https://github.com/rolldown/rolldown/blob/main/crates/rolldown_utils/src/ecmascript.rs#L14-L49
-    pub fn legitimize_identifier_name(name: &str) -> Cow<str> {
+    pub fn legitimize_identifier_name(name: &str) -> Cow<'_, str> {
         let mut legitimized = String::new();
         let mut chars_indices = name.char_indices();
 
         let mut first_invalid_char_index = None;
 
-        if let Some((idx, first_char)) = chars_indices.next() {
-            if !identifier::is_identifier_start(first_char) {
-                first_invalid_char_index = Some(idx);
-            }
+        if let Some((idx, first_char)) = chars_indices.next()
+            && !identifier::is_identifier_start(first_char)
+        {
+            first_invalid_char_index = Some(idx);
         }
 
         if first_invalid_char_index.is_none() {
@@ -850,227 +1472,241 @@ impl<'a> SystemJsTranspiler<'a> {
         Cow::Owned(legitimized)
     }
 
-    fn convert_import_to_function(&self, import: &mut ImportDeclaration<'a>) -> Expression<'a> {
-        let name = format!("_{}", Self::legitimize_identifier_name(import.source.value.as_str()).to_string());
-        let mut assigns = self.builder.vec();
-        if let Some(specifiers) = import.specifiers.take() {
-            for specifier in specifiers {
-                assigns.push(
-                    self.builder.statement_expression(
-                        SPAN,
-                        self.builder.expression_assignment(
-                            SPAN,
-                            AssignmentOperator::Assign,
-                            AssignmentTarget::from(
-                                self.builder
-                                    .simple_assignment_target_assignment_target_identifier(
-                                        SPAN,
-                                        specifier.local().name.clone_in(self.allocator),
-                                    ),
-                            ),
-                            match specifier {
-                                ImportDeclarationSpecifier::ImportDefaultSpecifier(_) => {
-                                    Expression::from(self.builder.member_expression_static(
-                                        SPAN,
-                                        self.builder.expression_identifier(
-                                            SPAN,
-                                            Atom::from_in(name.as_str(), self.allocator),
-                                        ),
-                                        self.builder.identifier_name(SPAN, "default"),
-                                        false,
-                                    ))
-                                }
-                                ImportDeclarationSpecifier::ImportNamespaceSpecifier(_) => {
-                                    self.builder.expression_identifier(
-                                        SPAN,
-                                        Atom::from_in(name.as_str(), self.allocator),
-                                    )
-                                }
-                                ImportDeclarationSpecifier::ImportSpecifier(specifier) => {
-                                    Expression::from(
-                                        self.builder.member_expression_static(
-                                            SPAN,
-                                            self.builder.expression_identifier(
-                                                SPAN,
-                                                Atom::from_in(name.as_str(), self.allocator),
-                                            ),
-                                            self.builder.identifier_name(
-                                                SPAN,
-                                                specifier
-                                                    .imported
-                                                    .identifier_name()
-                                                    .unwrap_or_else(|| specifier.local.name),
-                                            ),
-                                            false,
-                                        ),
-                                    )
-                                }
-                            },
-                        ),
+    /// Copies every name of the star-exported `namespace` onto `_exportObj`, following the ES
+    /// module rules for `export *`:
+    ///
+    /// ```js
+    /// _exportStar = _e; // only with several `export *` sources
+    /// var _exportObj = {};
+    /// for (var _key in _e) {
+    ///   if (
+    ///     _key !== "default" &&
+    ///     _key !== "__esModule" &&
+    ///     !Object.prototype.hasOwnProperty.call(_exportNames, _key)
+    ///   )
+    ///     _exportObj[_key] =
+    ///       _exportStar2 && _key in _exportStar2 && _exportStar2[_key] !== _e[_key]
+    ///         ? void 0
+    ///         : _e[_key];
+    /// }
+    /// ```
+    ///
+    /// Names the module exports itself take precedence. Names that several star sources provide
+    /// with different values are ambiguous: whichever setter runs once both sources are loaded
+    /// resets them to `undefined`, retracting the value the other setter may already have
+    /// exported, since SystemJS cannot remove a name once it has been exported.
+    fn build_export_star_statements(
+        &self,
+        namespace: Atom<'a>,
+        star_index: usize,
+    ) -> ArenaVec<'a, Statement<'a>> {
+        let mut stmts = self.builder.vec();
+        let key = || self.builder.expression_identifier(SPAN, self.export_key_name);
+        let namespace_ident = || self.builder.expression_identifier(SPAN, namespace);
+        let namespace_key = || {
+            Expression::from(self.builder.member_expression_computed(
+                SPAN,
+                namespace_ident(),
+                key(),
+                false,
+            ))
+        };
+        // _exportStar = _e;
+        if let Some(holder) = self.star_export_namespaces.get(star_index) {
+            stmts.push(self.builder.statement_expression(
+                SPAN,
+                self.builder.expression_assignment(
+                    SPAN,
+                    AssignmentOperator::Assign,
+                    AssignmentTarget::from(
+                        self.builder
+                            .simple_assignment_target_assignment_target_identifier(SPAN, *holder),
                     ),
-                );
-            }
+                    namespace_ident(),
+                ),
+            ));
         }
-        let body = self
-            .builder
-            .function_body(SPAN, self.builder.vec(), assigns);
-        self.builder.expression_function(
+        // var _exportObj = {};
+        stmts.push(Statement::from(self.builder.declaration_variable(
             SPAN,
-            FunctionType::FunctionExpression,
-            None,
-            false,
-            false,
+            VariableDeclarationKind::Var,
+            self.builder.vec1(self.builder.variable_declarator(
+                SPAN,
+                VariableDeclarationKind::Var,
+                self.builder.binding_pattern(
+                    self.builder
+                        .binding_pattern_kind_binding_identifier(SPAN, self.export_object_name),
+                    NONE,
+                    false,
+                ),
+                Some(self.builder.expression_object(SPAN, self.builder.vec())),
+                false,
+            )),
             false,
-            NONE,
-            NONE,
-            self.builder.formal_parameters(
+        )));
+        // _key !== "default" && _key !== "__esModule"
+        let mut condition = self.builder.expression_logical(
+            SPAN,
+            self.builder.expression_binary(
                 SPAN,
-                FormalParameterKind::FormalParameter,
-                self.builder.vec1(self.builder.formal_parameter(
+                key(),
+                BinaryOperator::StrictInequality,
+                self.builder.expression_string_literal(SPAN, "default", None),
+            ),
+            LogicalOperator::And,
+            self.builder.expression_binary(
+                SPAN,
+                key(),
+                BinaryOperator::StrictInequality,
+                self.builder.expression_string_literal(SPAN, "__esModule", None),
+            ),
+        );
+        // && !Object.prototype.hasOwnProperty.call(_exportNames, _key)
+        if let Some(export_names) = self.export_names_object {
+            let has_own_property = ["prototype", "hasOwnProperty", "call"].into_iter().fold(
+                self.builder.expression_identifier(SPAN, "Object"),
+                |object, property| {
+                    Expression::from(self.builder.member_expression_static(
+                        SPAN,
+                        object,
+                        self.builder.identifier_name(SPAN, property),
+                        false,
+                    ))
+                },
+            );
+            let mut args = self.builder.vec();
+            args.push(Argument::from(
+                self.builder.expression_identifier(SPAN, export_names),
+            ));
+            args.push(Argument::from(key()));
+            condition = self.builder.expression_logical(
+                SPAN,
+                condition,
+                LogicalOperator::And,
+                self.builder.expression_unary(
+                    SPAN,
+                    UnaryOperator::LogicalNot,
+                    self.builder
+                        .expression_call(SPAN, has_own_property, NONE, args, false),
+                ),
+            );
+        }
+        // _exportStar2 && _key in _exportStar2 && _exportStar2[_key] !== _e[_key] || ...
+        let mut ambiguous = None;
+        for (index, other) in self.star_export_namespaces.iter().enumerate() {
+            if index == star_index {
+                continue;
+            }
+            let other_ident = || self.builder.expression_identifier(SPAN, *other);
+            let conflict = self.builder.expression_logical(
+                SPAN,
+                self.builder.expression_logical(
+                    SPAN,
+                    other_ident(),
+                    LogicalOperator::And,
+                    self.builder
+                        .expression_binary(SPAN, key(), BinaryOperator::In, other_ident()),
+                ),
+                LogicalOperator::And,
+                self.builder.expression_binary(
+                    SPAN,
+                    Expression::from(self.builder.member_expression_computed(
+                        SPAN,
+                        other_ident(),
+                        key(),
+                        false,
+                    )),
+                    BinaryOperator::StrictInequality,
+                    namespace_key(),
+                ),
+            );
+            ambiguous = Some(match ambiguous {
+                Some(ambiguous) => {
+                    self.builder.expression_logical(SPAN, ambiguous, LogicalOperator::Or, conflict)
+                }
+                None => conflict,
+            });
+        }
+        // ambiguous ? void 0 : _e[_key]
+        let value = match ambiguous {
+            Some(ambiguous) => self.builder.expression_conditional(
+                SPAN,
+                ambiguous,
+                self.builder.void_0(SPAN),
+                namespace_key(),
+            ),
+            None => namespace_key(),
+        };
+        // for (var _key in _e) { if (...) _exportObj[_key] = value; }
+        let copy = self.builder.statement_expression(
+            SPAN,
+            self.builder.expression_assignment(
+                SPAN,
+                AssignmentOperator::Assign,
+                AssignmentTarget::from(self.builder.member_expression_computed(
+                    SPAN,
+                    self.builder.expression_identifier(SPAN, self.export_object_name),
+                    key(),
+                    false,
+                )),
+                value,
+            ),
+        );
+        stmts.push(self.builder.statement_for_in(
+            SPAN,
+            ast::ForStatementLeft::VariableDeclaration(self.builder.alloc_variable_declaration(
+                SPAN,
+                VariableDeclarationKind::Var,
+                self.builder.vec1(self.builder.variable_declarator(
                     SPAN,
-                    self.builder.vec(),
+                    VariableDeclarationKind::Var,
                     self.builder.binding_pattern(
-                        self.builder.binding_pattern_kind_binding_identifier(
-                            SPAN,
-                            Atom::from_in(name, self.allocator),
-                        ),
+                        self.builder
+                            .binding_pattern_kind_binding_identifier(SPAN, self.export_key_name),
                         NONE,
                         false,
                     ),
                     None,
                     false,
-                    false,
                 )),
-                NONE,
+                false,
+            )),
+            namespace_ident(),
+            self.builder.statement_block(
+                SPAN,
+                self.builder
+                    .vec1(self.builder.statement_if(SPAN, condition, copy, None)),
             ),
-            NONE,
-            Some(body),
-        )
+        ));
+        stmts
+    }
+
+    /// `_mod.name`, or `_mod["name"]` when `name` is not a valid identifier.
+    fn namespace_member(&self, namespace: Atom<'a>, name: Atom<'a>) -> ast::MemberExpression<'a> {
+        let object = self.builder.expression_identifier(SPAN, namespace);
+        if is_identifier_name(name.as_str()) {
+            self.builder.member_expression_static(
+                SPAN,
+                object,
+                self.builder.identifier_name(SPAN, name),
+                false,
+            )
+        } else {
+            self.builder.member_expression_computed(
+                SPAN,
+                object,
+                self.builder.expression_string_literal(SPAN, name, None),
+                false,
+            )
+        }
     }
 
-    fn convert_export_all_to_function(
+    /// `function (namespace) { ...body }`, one entry of the `setters` array.
+    fn setter_function(
         &self,
-        export: &mut ExportAllDeclaration<'a>,
+        namespace: Atom<'a>,
+        body: ArenaVec<'a, Statement<'a>>,
     ) -> Expression<'a> {
-        let name = format!("_{}", Self::legitimize_identifier_name(export.source.value.as_str()).to_string());
-        let body =
-            self.builder.function_body(
-                SPAN,
-                self.builder.vec(),
-                if let Some(_) = export.exported {
-                    self.builder.vec1(self.builder.statement_expression(
-                        SPAN,
-                        self.builder.expression_call(
-                            SPAN,
-                            self.builder.expression_identifier(SPAN, "_exports"),
-                            NONE,
-                            self.builder.vec1(Argument::from(
-                                self.builder.expression_object(SPAN, self.builder.vec()),
-                            )),
-                            false,
-                        ),
-                    ))
-                } else {
-                    let mut stmts = self.builder.vec();
-                    // var _exportObj = {};
-                    stmts.push(Statement::from(self.builder.declaration_variable(
-                        SPAN,
-                        VariableDeclarationKind::Var,
-                        self.builder.vec1(
-                            self.builder.variable_declarator(
-                                SPAN,
-                                VariableDeclarationKind::Var,
-                                self.builder.binding_pattern(
-                                    self.builder.binding_pattern_kind_binding_identifier(
-                                        SPAN,
-                                        "_exportObj",
-                                    ),
-                                    NONE,
-                                    false,
-                                ),
-                                Some(self.builder.expression_object(SPAN, self.builder.vec())),
-                                false,
-                            ),
-                        ),
-                        false,
-                    )));
-                    //       for (var _key in _e) {
-                    //         if (_key !== "default" && _key !== "__esModule") _exportObj[_key] = _e[_key];
-                    //       }
-                    stmts.push(self.builder.statement_for_in(
-                        SPAN,
-                        ast::ForStatementLeft::VariableDeclaration(
-                            self.builder.alloc_variable_declaration(
-                                SPAN,
-                                VariableDeclarationKind::Var,
-                                self.builder.vec1(self.builder.variable_declarator(
-                                    SPAN,
-                                    VariableDeclarationKind::Var,
-                                    self.builder.binding_pattern(
-                                        self.builder.binding_pattern_kind_binding_identifier(
-                                            SPAN,
-                                            Atom::from_in("_key", self.allocator),
-                                        ),
-                                        NONE,
-                                        false,
-                                    ),
-                                    None,
-                                    false,
-                                )),
-                                false,
-                            ),
-                        ),
-                        self.builder.expression_identifier(
-                            SPAN,
-                            Atom::from_in(name.as_str(), self.allocator),
-                        ),
-                        self.builder.statement_block(
-                            SPAN,
-                            self.builder.vec1(self.builder.statement_expression(
-                                SPAN,
-                                self.builder.expression_assignment(
-                                    SPAN,
-                                    AssignmentOperator::Assign,
-                                    AssignmentTarget::from(self.builder.member_expression_static(
-                                        SPAN,
-                                        self.builder.expression_identifier(SPAN, "_exportObj"),
-                                        self.builder.identifier_name(SPAN, "_key"),
-                                        false,
-                                    )),
-                                    Expression::from(self.builder.member_expression_static(
-                                        SPAN,
-                                        self.builder.expression_identifier(
-                                            SPAN,
-                                            Atom::from_in(name.as_str(), self.allocator),
-                                        ),
-                                        self.builder.identifier_name(SPAN, "_key"),
-                                        false,
-                                    )),
-                                ),
-                            )),
-                        ),
-                    ));
-                    // _export(_exportObj);
-                    stmts.push(
-                        self.builder.statement_expression(
-                            SPAN,
-                            self.builder.expression_call(
-                                SPAN,
-                                self.builder.expression_identifier(SPAN, "_exports"),
-                                NONE,
-                                self.builder.vec1(Argument::from(
-                                    self.builder.expression_identifier(
-                                        SPAN,
-                                        Atom::from_in("_exportObj", self.allocator),
-                                    ),
-                                )),
-                                false,
-                            ),
-                        ),
-                    );
-                    stmts
-                },
-            );
         self.builder.expression_function(
             SPAN,
             FunctionType::FunctionExpression,
@@ -1083,44 +1719,208 @@ impl<'a> SystemJsTranspiler<'a> {
             self.builder.formal_parameters(
                 SPAN,
                 FormalParameterKind::FormalParameter,
-                self.builder.vec1(self.builder.formal_parameter(
-                    SPAN,
-                    self.builder.vec(),
-                    self.builder.binding_pattern(
-                        self.builder.binding_pattern_kind_binding_identifier(
-                            SPAN,
-                            Atom::from_in(name, self.allocator),
-                        ),
-                        NONE,
-                        false,
-                    ),
-                    None,
-                    false,
-                    false,
-                )),
+                self.builder.vec1(self.formal_parameter(namespace)),
                 NONE,
             ),
             NONE,
-            Some(body),
+            Some(self.builder.function_body(SPAN, self.builder.vec(), body)),
         )
     }
 
-    fn convert_import_map(&mut self) -> ArenaVec<'a, Expression<'a>> {
-        let mut converted_imports = self.builder.vec();
-        for import in self.imports.take_in(self.allocator).iter_mut() {
-            match import {
-                ImportMap::ImportDeclaration(import_decl) => {
-                    converted_imports.push(
-                        self.convert_import_to_function(&mut import_decl.take_in(self.allocator)),
-                    );
+    /// The setter of a dependency: assigns every binding imported from it and forwards all of its
+    /// re-exports through a single `_export` call.
+    fn convert_dependency_to_setter(
+        &self,
+        dependency: &mut Dependency<'a>,
+        star_index: Option<usize>,
+    ) -> Expression<'a> {
+        let namespace = dependency.namespace;
+        let mut stmts = self.builder.vec();
+        let mut reexports = vec![];
+        for entry in dependency.entries.iter_mut() {
+            match entry {
+                ImportMap::ImportDeclaration(import) => {
+                    for specifier in import.specifiers.iter().flatten() {
+                        let value = match specifier {
+                            ImportDeclarationSpecifier::ImportDefaultSpecifier(_) => {
+                                Expression::from(
+                                    self.namespace_member(namespace, Atom::from("default")),
+                                )
+                            }
+                            ImportDeclarationSpecifier::ImportNamespaceSpecifier(_) => {
+                                self.builder.expression_identifier(SPAN, namespace)
+                            }
+                            ImportDeclarationSpecifier::ImportSpecifier(specifier) => {
+                                Expression::from(
+                                    self.namespace_member(namespace, specifier.imported.name()),
+                                )
+                            }
+                        };
+                        // local = _mod.imported; or _export("a", a = _mod.a) if re-exported
+                        let local = specifier.local().name;
+                        let assignment = self.builder.expression_assignment(
+                            SPAN,
+                            AssignmentOperator::Assign,
+                            AssignmentTarget::from(
+                                self.builder
+                                    .simple_assignment_target_assignment_target_identifier(
+                                        SPAN, local,
+                                    ),
+                            ),
+                            value,
+                        );
+                        stmts.push(self.builder.statement_expression(
+                            SPAN,
+                            self.export_binding(&local, assignment),
+                        ));
+                    }
                 }
-                ImportMap::ExportAllDeclaration(export_all_decl) => {
-                    converted_imports.push(self.convert_export_all_to_function(
-                        &mut export_all_decl.take_in(self.allocator),
-                    ))
+                ImportMap::ExportNamedDeclaration(export) => {
+                    // export { local as exported } from "mod"
+                    reexports.extend(export.specifiers.iter().map(|spec| {
+                        (
+                            spec.exported.name(),
+                            Expression::from(self.namespace_member(namespace, spec.local.name())),
+                        )
+                    }));
                 }
+                ImportMap::ExportAllDeclaration(export) => {
+                    // export * as exported from "mod"
+                    if let Some(exported) = &export.exported {
+                        reexports.push((
+                            exported.name(),
+                            self.builder.expression_identifier(SPAN, namespace),
+                        ));
+                    }
+                }
+            }
+        }
+        if let Some(star_index) = star_index {
+            stmts.extend(self.build_export_star_statements(namespace, star_index));
+            // _exportObj.exported = value;
+            for (exported, value) in reexports {
+                let target = AssignmentTarget::from(
+                    self.namespace_member(self.export_object_name, exported),
+                );
+                stmts.push(self.builder.statement_expression(
+                    SPAN,
+                    self.builder
+                        .expression_assignment(SPAN, AssignmentOperator::Assign, target, value),
+                ));
+            }
+            // _export(_exportObj);
+            stmts.push(self.builder.statement_expression(
+                SPAN,
+                self.builder.expression_call(
+                    SPAN,
+                    self.export_function(),
+                    NONE,
+                    self.builder.vec1(Argument::from(
+                        self.builder.expression_identifier(SPAN, self.export_object_name),
+                    )),
+                    false,
+                ),
+            ));
+        } else if !reexports.is_empty() {
+            // _export({ exported: value, ... });
+            let properties = self.builder.vec_from_iter(reexports.into_iter().map(
+                |(exported, value)| {
+                    self.builder.object_property_kind_object_property(
+                        SPAN,
+                        PropertyKind::Init,
+                        self.property_key(exported),
+                        value,
+                        false,
+                        false,
+                        false,
+                    )
+                },
+            ));
+            stmts.push(self.builder.statement_expression(
+                SPAN,
+                self.builder.expression_call(
+                    SPAN,
+                    self.export_function(),
+                    NONE,
+                    self.builder.vec1(Argument::from(
+                        self.builder.expression_object(SPAN, properties),
+                    )),
+                    false,
+                ),
+            ));
+        }
+        self.setter_function(namespace, stmts)
+    }
+
+    fn convert_dependencies(&mut self) -> ArenaVec<'a, Expression<'a>> {
+        let mut star_index = 0;
+        let mut dependencies = self.dependencies.take_in(self.allocator);
+        let setters = self
+            .builder
+            .vec_from_iter(dependencies.iter_mut().map(|dependency| {
+                let has_star_export = dependency.entries.iter().any(|entry| {
+                    matches!(entry, ImportMap::ExportAllDeclaration(export) if export.exported.is_none())
+                });
+                let index = has_star_export.then(|| {
+                    star_index += 1;
+                    star_index - 1
+                });
+                self.convert_dependency_to_setter(dependency, index)
+            }));
+        // Keep the (now emptied) dependencies around for their sources.
+        self.dependencies = dependencies;
+        setters
+    }
+
+    /// Records `entry` under the dependency on `source`, so that a module imported or re-exported
+    /// several times is listed once and gets a single setter.
+    fn add_dependency(&mut self, source: &StringLiteral<'a>, entry: ImportMap<'a>) {
+        match self
+            .dependencies
+            .iter_mut()
+            .find(|dependency| dependency.source.value == source.value)
+        {
+            Some(dependency) => dependency.entries.push(entry),
+            None => {
+                let name = format!("_{}", Self::legitimize_identifier_name(source.value.as_str()));
+                let namespace = self.used_names.unused_name(&name, self.allocator);
+                self.dependencies.push(Dependency {
+                    source: source.clone_in(self.allocator),
+                    namespace,
+                    entries: self.builder.vec1(entry),
+                });
             }
         }
-        converted_imports
+    }
+}
+
+/// Every name bound or referenced in the module, plus the ones we generated, so that the names we
+/// generate never shadow (or get shadowed by) user code or each other.
+pub struct UsedNames<'a> {
+    names: HashSet<Atom<'a>>,
+}
+
+impl<'a> UsedNames<'a> {
+    /// Seeds the set with every symbol of the module and every global it references.
+    pub fn from_scoping(scoping: &Scoping, allocator: &'a Allocator) -> Self {
+        let names = scoping
+            .symbol_names()
+            .chain(scoping.root_unresolved_references().keys().copied())
+            .map(|name| Atom::from_in(name, allocator))
+            .collect();
+        Self { names }
+    }
+
+    /// Returns `base`, or `base2`, `base3`, ... if it is already taken, and reserves it.
+    pub fn unused_name(&mut self, base: &str, allocator: &'a Allocator) -> Atom<'a> {
+        let mut candidate = Cow::Borrowed(base);
+        let mut suffix = 1;
+        while self.names.contains(candidate.as_ref()) {
+            suffix += 1;
+            candidate = Cow::Owned(format!("{base}{suffix}"));
+        }
+        let name = Atom::from_in(candidate.as_ref(), allocator);
+        self.names.insert(name);
+        name
     }
 }
@@ -6,9 +6,29 @@ System.register([
 	"hello.world",
 	"c",
 	"hello_world"
-], function() {
+], function(_export, _context) {
 	"use strict";
-	var f, _asyncToGenerator, hello, w, hellow, B, A, C, e, asp, a2, b2, d2, a23, b23, d23, sfd, sdfewfsdvcx, afsj, vsa, sdvncjx, dsjlndkb, fsd, a9, b9, sadfsdaf, asdnjk, afiuwdfjsvnk, a1234, b10, wfeasdxvxdvcx, svjnkxc, sdcx;
+	var f, _asyncToGenerator, hello, w, helloWorld, B, A, C, e, asp, a2, b2, d2, a23, b23, d23, sfd, sdfewfsdvcx, afsj, vsa, sdvncjx, dsjlndkb, fsd, a9, b9, d1234, sadfsdaf, asdnjk, afiuwdfjsvnk, a1234, b10, wfeasdxvxdvcx, svjnkxc, sdcx;
+	var _exportNames = {
+		d: true,
+		e: true,
+		b: true,
+		afsj: true,
+		sixmco: true,
+		fsd: true,
+		afnsjkv: true,
+		B: true,
+		a9: true,
+		b9: true,
+		d1234: true,
+		a1234: true,
+		b10: true,
+		A: true,
+		wfeasdxvxdvcx: true,
+		default: true,
+		sdcx: true,
+		b2222: true
+	};
 	function b() {
 		this.b = 2;
 		let sfvdkmcx = "23rewfdsvcx";
@@ -23,7 +43,7 @@ System.register([
 	function b2222() {
 		return hello.apply(this, arguments);
 	}
-	_exports({
+	_export({
 		b,
 		afsj,
 		sixmco: asp,
@@ -42,32 +62,28 @@ System.register([
 				_asyncToGenerator = ___oxc_project_runtime_helpers_asyncToGenerator.default;
 			},
 			function(_b) {
-				_exports({});
+				_export({ d: _b });
 			},
 			function(_e) {
 				var _exportObj = {};
 				for (var _key in _e) {
-					_exportObj._key = _e._key;
+					if (_key !== "default" && _key !== "__esModule" && !Object.prototype.hasOwnProperty.call(_exportNames, _key)) _exportObj[_key] = _e[_key];
 				}
-				_exports(_exportObj);
+				_export(_exportObj);
 			},
 			function(_hello_world) {},
 			function(_c) {
 				hello = _c.hello;
 				w = _c.d;
 			},
-			function(_hello_world) {
-				hellow = _hello_world.default;
+			function(_hello_world2) {
+				helloWorld = _hello_world2.default;
 			}
 		],
 		execute: function() {
-			_exports("e", e = 3);
-			asp = 234;
+			_export("e", e = 3);
+			_export("sixmco", asp = 234);
 			(void 0).a = 1;
-			function b() {
-				this.b = 2;
-				let sfvdkmcx = "23rewfdsvcx";
-			}
 			({a2, b2, e2: {c2: d2}} = " sasd");
 			({a23, b23, e23: {c2: d23}, ["x"]: sfd} = " sasd");
 			sdfewfsdvcx = sujdnvkkjjk = 2;
@@ -75,11 +91,11 @@ System.register([
 				(void 0).c = 2;
 				let wosdvjcxk = "sdfvcxjnk";
 			}
-			afsj = 3432;
+			_export("afsj", afsj = 3432);
 			vsa = 2342341, sdvncjx = 28405982459732;
 			dsjlndkb = 234323984;
-			_exports("fsd", fsd = 234342234);
-			_exports("B", B = class {
+			_export("fsd", fsd = 234342234);
+			_export("B", B = class B {
 				constructor() {
 					console.log(234);
 				}
@@ -88,16 +104,16 @@ System.register([
 				1,
 				2,
 				3
-			], _exports("a9", a9), _exports("b9", b9), _exports("d1234", d1234);
+			], _export("a9", a9), _export("b9", b9), _export("d1234", d1234);
 			[sadfsdaf, asdnjk, afiuwdfjsvnk] = [
 				123,
 				42,
 				32
 			];
-			({a10: a1234, b10} = {}), _exports("a1234", a1234), _exports("b10", b10);
-			_exports("A", A = class {});
-			C = class {};
-			({aasfdfsdafd: {wfeasdxvxdvcx} = sfdomivxcokfesd} = sdamovkxsdfsd);
+			({a10: a1234, b10} = {}), _export("a1234", a1234), _export("b10", b10);
+			_export("A", A = class A {});
+			C = class C {};
+			({aasfdfsdafd: {wfeasdxvxdvcx} = sfdomivxcokfesd} = sdamovkxsdfsd), _export("wfeasdxvxdvcx", wfeasdxvxdvcx);
 			svjnkxc = function() {
 				var _ref = _asyncToGenerator(function* () {
 					console.log(void 0);
@@ -106,10 +122,7 @@ System.register([
 					return _ref.apply(this, arguments);
 				};
 			}();
-			function helloworld() {
-				console.log(void 2);
-			}
-			_exports("sdcx", sdcx = helloworld.apply(3));
+			_export("sdcx", sdcx = helloworld.apply(3));
 		}
 	};
 });